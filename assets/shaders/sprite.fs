@@ -1,11 +1,13 @@
 #version 140
 
 in vec2 v_uv;
+in vec4 v_color;
 out vec4 color;
 
 uniform sampler2D colormap;
+uniform vec4 tint;
 
 void main() {
     vec4 pix = texture(colormap, v_uv);
-    color = pix;
+    color = pix * v_color * tint;
 }
@@ -2,15 +2,24 @@
 
 in vec2 offset;
 in vec2 uv;
+
+in vec2 position;
+in vec2 size;
+in vec2 uv_offset;
+in vec2 uv_scale;
+in vec4 sprite_color;
+in float layer;
+
 out vec2 v_uv;
+out vec4 v_color;
 
 uniform vec2 projection;
 uniform vec2 camera_pos;
-uniform vec2 position;
-uniform vec2 uv_offset;
-uniform vec2 uv_scale;
 
 void main() {
     v_uv = (uv * uv_scale) + uv_offset;
-    gl_Position = vec4((position + offset - camera_pos) * projection, 0.0, 1.0);
+    v_color = sprite_color;
+    // Higher layers are nearer, though the batch's order is what stacks them.
+    float depth = 0.5 - layer / 64.0;
+    gl_Position = vec4((position + offset * size - camera_pos) * projection, depth, 1.0);
 }
@@ -0,0 +1,42 @@
+#version 140
+
+in vec2 v_uv;
+out vec4 color;
+
+uniform sampler2D outgoing;
+uniform sampler2D incoming;
+// 0 fades, 1 wipes, 2 closes an iris on the focus and opens it again, and
+// 3 dissolves pixel by pixel.
+uniform int kind;
+uniform float progress;
+// Where the iris closes, from 0 to 1 across the screen.
+uniform vec2 focus;
+uniform vec2 resolution;
+
+float noise(vec2 pixel) {
+    return fract(sin(dot(pixel, vec2(12.9898, 78.233))) * 43758.5453);
+}
+
+void main() {
+    vec4 before = texture(outgoing, v_uv);
+    vec4 after = texture(incoming, v_uv);
+    vec2 pixel = floor(v_uv * resolution);
+
+    if (kind == 1) {
+        color = mix(before, after, step(pixel.x, progress * resolution.x));
+    } else if (kind == 2) {
+        float reach = length(resolution);
+        vec4 black = vec4(0.0, 0.0, 0.0, 1.0);
+        if (progress < 0.5) {
+            float radius = reach * (1.0 - progress * 2.0);
+            color = mix(black, before, step(length(pixel - focus * resolution), radius));
+        } else {
+            float radius = reach * (progress * 2.0 - 1.0) * 0.5;
+            color = mix(black, after, step(length(pixel - 0.5 * resolution), radius));
+        }
+    } else if (kind == 3) {
+        color = mix(before, after, step(noise(pixel), progress));
+    } else {
+        color = mix(before, after, progress);
+    }
+}
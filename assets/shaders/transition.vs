@@ -0,0 +1,10 @@
+#version 140
+
+in vec2 offset;
+in vec2 uv;
+out vec2 v_uv;
+
+void main() {
+    v_uv = uv;
+    gl_Position = vec4(offset * 2.0, 0.0, 1.0);
+}
@@ -0,0 +1,706 @@
+use std::cmp::max;
+use std::collections::BTreeMap;
+use std::env::current_exe;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use serde_yaml;
+
+use camera::CameraMode;
+use prefabs::PrefabTable;
+use systems::motion::{Collision, class_mask};
+use vectors::*;
+
+pub mod packs;
+pub mod tiled;
+pub mod watch;
+pub mod xsb;
+
+/// Where assets come from when they've been put somewhere other than next
+/// to the game.
+static ASSETS_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Loads assets from `path` instead, for the rest of the run. Only the first
+/// call counts.
+pub fn set_assets_folder(path: PathBuf)
+{
+    let _ = ASSETS_OVERRIDE.set(path);
+}
+
+pub fn assets_folder() -> io::Result<PathBuf>
+{
+    if let Some(path) = ASSETS_OVERRIDE.get()
+    {
+        Ok(path.clone())
+    }
+    else if cfg!(debug_assertions)
+    {
+        debug_path_to_assets()
+    }
+    else
+    {
+        release_path_to_assets()
+    }
+}
+
+
+fn debug_path_to_assets() -> io::Result<PathBuf>
+{
+    let mut path = current_exe()?;
+    path.pop();
+    path.pop();
+    path.pop();
+    path.push("assets");
+    Ok(path)
+}
+
+
+fn release_path_to_assets() -> io::Result<PathBuf>
+{
+    let mut path = current_exe()?;
+    path.pop();
+    path.push("assets");
+    Ok(path)
+}
+
+pub fn get_asset_path<P>(path: P) -> PathBuf
+    where P: AsRef<Path>
+{
+    let mut filepath = assets_folder().expect("Could not find assets folder");
+    filepath.push(path.as_ref());
+    filepath
+}
+
+pub fn get_asset_string<P>(path: P) -> String
+    where P: AsRef<Path>
+{
+    let path = get_asset_path(path);
+    let mut file = File::open(&path).expect(&format!("Could not open file '{:?}'", path));
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).expect(&format!("Failed to read file '{:?}'", path));
+    contents
+}
+
+/// Like `get_asset_string`, but for files that might be mid-edit.
+pub fn read_asset_string<P>(path: P) -> Result<String, String>
+    where P: AsRef<Path>
+{
+    let path = get_asset_path(path);
+    let mut contents = String::new();
+    File::open(&path).and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|e| format!("Could not read '{}': {}", path.display(), e))?;
+    Ok(contents)
+}
+
+pub fn read_asset_bytes<P>(path: P) -> Result<Vec<u8>, String>
+    where P: AsRef<Path>
+{
+    let path = get_asset_path(path);
+    let mut contents = Vec::new();
+    File::open(&path).and_then(|mut file| file.read_to_end(&mut contents))
+        .map_err(|e| format!("Could not read '{}': {}", path.display(), e))?;
+    Ok(contents)
+}
+
+pub fn get_asset_bytes<P>(path: P) -> Vec<u8>
+    where P: AsRef<Path>
+{
+    let path = get_asset_path(path);
+    let mut file = File::open(&path).expect(&format!("Could not open file '{:?}'", path));
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents).expect(&format!("Failed to read file '{:?}'", path));
+    contents
+}
+
+
+pub const LEVEL_FORMAT_VERSION: u32 = 2;
+
+pub fn load_levels<P>(path: P, prefabs: &PrefabTable) -> Vec<Level>
+    where P: AsRef<Path>
+{
+    parse_levels(&get_asset_string(path), prefabs)
+}
+
+/// Loads every level in a YAML level file or a Sokoban pack, or the single
+/// level in a Tiled map.
+pub fn load_levels_any<P>(path: P, prefabs: &PrefabTable) -> Vec<Level>
+    where P: AsRef<Path>
+{
+    let path = path.as_ref();
+    match path.extension().and_then(|ext| ext.to_str())
+    {
+        Some("tmx") | Some("json") => vec![tiled::load_tiled_level(get_asset_path(path), prefabs).expect("Failed to load Tiled map")],
+        Some("xsb") | Some("sok") => xsb::load_xsb_levels(get_asset_path(path), false, prefabs).expect("Failed to load Sokoban levels"),
+        _ => load_levels(path, prefabs)
+    }
+}
+
+/// Loads levels without panicking, for reloading files that might be
+/// half-written or broken.
+pub fn try_load_levels<P>(path: P, prefabs: &PrefabTable) -> Result<Vec<Level>, String>
+    where P: AsRef<Path>
+{
+    try_parse_levels(&read_asset_string(path)?, prefabs)
+}
+
+pub fn parse_levels(yaml: &str, prefabs: &PrefabTable) -> Vec<Level>
+{
+    try_parse_levels(yaml, prefabs).unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub fn try_parse_levels(yaml: &str, prefabs: &PrefabTable) -> Result<Vec<Level>, String>
+{
+    let levelset: LevelSet = serde_yaml::from_str(yaml).map_err(|e| format!("Failed to parse levels: {}", e))?;
+    if levelset.version > LEVEL_FORMAT_VERSION
+    {
+        return Err(format!("Unsupported level file version {}", levelset.version));
+    }
+
+    let (version, collision) = (levelset.version, levelset.collision);
+    for (code, data) in &collision
+    {
+        data.check().map_err(|e| format!("Bad collision for '{}': {}", code, e))?;
+    }
+    levelset.levels.into_iter()
+        .map(|leveldata| build_level(leveldata, version, &collision, prefabs))
+        .collect()
+}
+
+/// Writes levels out in the current level file format.
+pub fn levels_to_yaml(levels: &[Level]) -> String
+{
+    let levelset = LevelSet
+    {
+        version: LEVEL_FORMAT_VERSION,
+        collision: BTreeMap::new(),
+        levels: levels.iter().map(level_data).collect()
+    };
+    serde_yaml::to_string(&levelset).expect("Failed to write levels")
+}
+
+pub fn save_levels<P>(levels: &[Level], path: P) -> io::Result<()>
+    where P: AsRef<Path>
+{
+    let mut file = File::create(path)?;
+    file.write_all(levels_to_yaml(levels).as_bytes())
+}
+
+fn level_data(level: &Level) -> LevelData
+{
+    let rows = |layer| level.layer_rows(layer).iter().map(|row| row.join(" ")).collect();
+    LevelData
+    {
+        name: level.name.clone(),
+        meta: level.meta.clone(),
+        collision: level.collision.clone(),
+        links: level.links.iter().map(|link| LinkData { button: level_to_grid(link.button, level.height), gate: level_to_grid(link.gate, level.height) }).collect(),
+        terrain: rows(TileLayer::Terrain),
+        objects: rows(TileLayer::Objects),
+        actors: rows(TileLayer::Actors),
+        .. Default::default()
+    }
+}
+
+fn build_level(leveldata: LevelData, version: u32, file_collision: &BTreeMap<String, CollisionData>, prefabs: &PrefabTable) -> Result<Level, String>
+{
+    let mut grids = Vec::new();
+    if version < 2
+    {
+        grids.push((None, &leveldata.tiles));
+    }
+    else
+    {
+        grids.push((Some(TileLayer::Terrain), &leveldata.terrain));
+        grids.push((Some(TileLayer::Objects), &leveldata.objects));
+        grids.push((Some(TileLayer::Actors), &leveldata.actors));
+    }
+
+    let height = grids.iter().map(|&(_, rows)| rows.len()).max().unwrap_or(0);
+    let mut player_pos = None;
+    let mut stalker_pos = None;
+    let mut has_goal = false;
+    let mut has_button = false;
+    let mut tiles = Vec::new();
+    let mut width = 0;
+
+    for (layer, rows) in grids
+    {
+        for (inv_y, row) in rows.iter().enumerate()
+        {
+            let y = height - inv_y - 1;
+            for (x, code) in row.split(' ').enumerate()
+            {
+                width = max(width, x);
+                let tilepos = vec2(x as f32, y as f32);
+                if code == "."
+                {
+                    continue;
+                }
+                let prefab = match prefabs.get(code)
+                {
+                    Some(prefab) => prefab,
+                    None => return Err(format!("Found tile code with no prefab in level '{}': '{}'", leveldata.name, code))
+                };
+
+                if prefabs.has_component(code, |e| e.player)
+                {
+                    player_pos = Some(tilepos);
+                }
+                if prefabs.has_component(code, |e| e.tracker.is_some())
+                {
+                    stalker_pos = Some(tilepos);
+                }
+                has_goal |= prefabs.has_component(code, |e| e.goal);
+                has_button |= prefabs.has_component(code, |e| e.button);
+
+                // Version 1 files only have the one grid, so each tile goes
+                // on whichever layer its prefab usually lives on.
+                let layer = layer.unwrap_or_else(|| prefab.tile_layer());
+                tiles.push(Tile { code: code.to_string(), pos: tilepos, layer: layer });
+            }
+        }
+    }
+
+    for (code, data) in &leveldata.collision
+    {
+        data.check().map_err(|e| format!("Bad collision for '{}' in level '{}': {}", code, leveldata.name, e))?;
+    }
+    let mut collision = file_collision.clone();
+    collision.extend(leveldata.collision);
+
+    let mut meta = leveldata.meta;
+    if meta.par.is_none()
+    {
+        meta.par = leveldata.par;
+    }
+
+    let links = leveldata.links.iter()
+        .map(|link| Link { button: grid_to_level(link.button, height), gate: grid_to_level(link.gate, height) })
+        .collect();
+
+    let midpoint = vec2(width as f32, height as f32) * 0.5 + vec2(0.0, -0.5);
+    if let Some(ref camera) = meta.camera
+    {
+        CameraMode::from_name(camera).ok_or(format!("Unknown camera '{}' in level '{}'", camera, leveldata.name))?;
+    }
+    match meta.objective
+    {
+        Objective::Exit if !has_goal => return Err(format!("No doors in level '{}'", leveldata.name)),
+        Objective::Buttons if !has_button => return Err(format!("No buttons in level '{}'", leveldata.name)),
+        _ => ()
+    }
+
+    let player_pos = match player_pos
+    {
+        Some(pos) => pos,
+        None => return Err(format!("No player position in level '{}'", leveldata.name))
+    };
+
+    let mut initial_stalker_path = Vec::new();
+    if let Some(stalker_pos) = stalker_pos
+    {
+        let disp = (player_pos - stalker_pos).round_i32();
+        if disp.components[0] != 0 && disp.components[1] != 0
+        {
+            return Err(format!("Stalker must be in a straight line from player in level '{}'", leveldata.name));
+        }
+
+        let steps = max(disp.components[0].abs(), disp.components[1].abs());
+        let delta = vec2(disp.components[0] / steps, disp.components[1] / steps);
+        for i in 1..steps
+        {
+            initial_stalker_path.push(stalker_pos.round_i32() + (delta * i));
+        }
+    }
+
+    Ok(Level
+    {
+        name: leveldata.name,
+        meta: meta,
+        width: width + 1,
+        height: height,
+        midpoint: midpoint,
+        player_pos: player_pos,
+        stalker_pos: stalker_pos,
+        tiles: tiles,
+        links: links,
+        collision: collision,
+        initial_stalker_path: initial_stalker_path
+    })
+}
+
+/// Level files count rows down from the top, but levels count up from the
+/// bottom.
+fn grid_to_level(cell: [u32; 2], height: usize) -> Vector2<f32>
+{
+    vec2(cell[0] as f32, (height as f32) - (cell[1] as f32) - 1.0)
+}
+
+fn level_to_grid(pos: Vector2<f32>, height: usize) -> [u32; 2]
+{
+    let pos = pos.round_i32();
+    [pos.components[0] as u32, (height as i32 - pos.components[1] - 1) as u32]
+}
+
+fn default_version() -> u32
+{
+    1
+}
+
+#[derive(Serialize, Deserialize)]
+struct LevelSet
+{
+    #[serde(default = "default_version")]
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub collision: BTreeMap<String, CollisionData>,
+    pub levels: Vec<LevelData>
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct LevelData
+{
+    pub name: String,
+    #[serde(default)]
+    pub meta: LevelMeta,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub collision: BTreeMap<String, CollisionData>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<LinkData>,
+
+    // Version 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub par: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tiles: Vec<String>,
+
+    // Version 2
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub terrain: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub objects: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub actors: Vec<String>
+}
+
+/// A button and a gate it opens, as [column, row] counting from the top left.
+#[derive(Serialize, Deserialize, Clone)]
+struct LinkData
+{
+    pub button: [u32; 2],
+    pub gate: [u32; 2]
+}
+
+/// Extra information about a level that the game can use, but which doesn't
+/// change what's in it.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct LevelMeta
+{
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub par: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub music: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Objective::is_exit")]
+    pub objective: Objective
+}
+
+/// What the player has to do to finish a level.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Objective
+{
+    /// Walk through a door.
+    #[default]
+    #[serde(rename = "exit")]
+    Exit,
+    /// Push a block onto every button, like in Sokoban.
+    #[serde(rename = "buttons")]
+    Buttons
+}
+
+impl Objective
+{
+    pub fn is_exit(&self) -> bool
+    {
+        *self == Objective::Exit
+    }
+
+    pub fn name(&self) -> &'static str
+    {
+        match *self
+        {
+            Objective::Exit => "exit",
+            Objective::Buttons => "buttons"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Objective>
+    {
+        match name
+        {
+            "exit" => Some(Objective::Exit),
+            "buttons" => Some(Objective::Buttons),
+            _ => None
+        }
+    }
+}
+
+
+/// Overrides for the collision of every tile with a given code. Each list
+/// names classes ("player", "stalker", "block", "all" or "none"), and any
+/// list left out keeps the tile's usual value.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct CollisionData
+{
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blocks: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pushed_by: Option<Vec<String>>
+}
+
+impl CollisionData
+{
+    pub fn apply(&self, base: Collision) -> Collision
+    {
+        Collision
+        {
+            class: self.class.as_ref().map_or(base.class, |names| parse_class_names(names)),
+            blocks: self.blocks.as_ref().map_or(base.blocks, |names| parse_class_names(names)),
+            pushed_by: self.pushed_by.as_ref().map_or(base.pushed_by, |names| parse_class_names(names))
+        }
+    }
+
+    /// Makes sure every class named is one we know, since `apply` panics on
+    /// any it doesn't.
+    pub fn check(&self) -> Result<(), String>
+    {
+        let lists = [&self.class, &self.blocks, &self.pushed_by];
+        for name in lists.iter().filter_map(|list| list.as_ref()).flat_map(|names| names.iter())
+        {
+            if class_mask(name).is_none()
+            {
+                return Err(format!("Unknown collision class '{}'", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_class_names(names: &[String]) -> u32
+{
+    names.iter().fold(0, |mask, name| mask | class_mask(name).expect(&format!("Unknown collision class '{}'", name)))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Level
+{
+    pub name: String,
+    pub meta: LevelMeta,
+    pub width: usize,
+    pub height: usize,
+    pub midpoint: Vector2<f32>,
+    pub player_pos: Vector2<f32>,
+    pub stalker_pos: Option<Vector2<f32>>,
+    pub tiles: Vec<Tile>,
+    pub links: Vec<Link>,
+    pub collision: BTreeMap<String, CollisionData>,
+    pub initial_stalker_path: Vec<Vector2<i32>>
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TileLayer
+{
+    Terrain,
+    Objects,
+    Actors
+}
+
+impl TileLayer
+{
+    pub fn all() -> [TileLayer; 3]
+    {
+        [TileLayer::Terrain, TileLayer::Objects, TileLayer::Actors]
+    }
+
+    pub fn name(&self) -> &'static str
+    {
+        match *self
+        {
+            TileLayer::Terrain => "terrain",
+            TileLayer::Objects => "objects",
+            TileLayer::Actors => "actors"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<TileLayer>
+    {
+        match name
+        {
+            "terrain" => Some(TileLayer::Terrain),
+            "objects" => Some(TileLayer::Objects),
+            "actors" => Some(TileLayer::Actors),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tile
+{
+    pub code: String,
+    pub pos: Vector2<f32>,
+    pub layer: TileLayer
+}
+
+/// Gates with links only open for the buttons they're linked to. Gates
+/// without any open for every button in the level.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Link
+{
+    pub button: Vector2<f32>,
+    pub gate: Vector2<f32>
+}
+
+impl Level
+{
+    /// The codes on one layer, as rows of a grid from the top down.
+    pub fn layer_rows(&self, layer: TileLayer) -> Vec<Vec<String>>
+    {
+        let mut rows = vec![vec![".".to_string(); self.width]; self.height];
+        for tile in self.tiles.iter().filter(|t| t.layer == layer)
+        {
+            let cell = level_to_grid(tile.pos, self.height);
+            rows[cell[1] as usize][cell[0] as usize] = tile.code.clone();
+        }
+        rows
+    }
+
+    /// Builds the level again from its tiles, working out everything else
+    /// afresh. Used after the tiles have been edited.
+    pub fn rebuild(&self, prefabs: &PrefabTable) -> Result<Level, String>
+    {
+        build_level(level_data(self), LEVEL_FORMAT_VERSION, &BTreeMap::new(), prefabs)
+    }
+
+    pub fn links_to(&self, gate: Vector2<f32>) -> Vec<Vector2<i32>>
+    {
+        self.links.iter().filter(|link| link.gate == gate).map(|link| link.button.round_i32()).collect()
+    }
+
+    /// The collision for tiles with the given code, after applying any
+    /// overrides from the level file.
+    pub fn collision(&self, code: &str, default: Collision) -> Collision
+    {
+        match self.collision.get(code)
+        {
+            Some(data) => data.apply(default),
+            None => default
+        }
+    }
+}
+
+/// Finds a level by its name, or by its number counting from 1.
+pub fn find_level(levels: &[Level], name: &str) -> Option<usize>
+{
+    match name.parse::<usize>()
+    {
+        Ok(number) if number >= 1 && number <= levels.len() => Some(number - 1),
+        _ => levels.iter().position(|level| level.name.eq_ignore_ascii_case(name))
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use serde_yaml;
+
+    fn test_prefabs() -> PrefabTable
+    {
+        serde_yaml::from_str("
+            prefabs:
+                P: { name: Player, layer: actors, entities: [{ player: true }] }
+                S: { name: Stalker, layer: actors, entities: [{ tracker: { delay: 0.0 } }] }
+                D: { name: Door, entities: [{ goal: true }] }
+                B: { name: Block, layer: objects, entities: [{}] }
+                b: { name: Button, entities: [{ button: true }] }
+                '=': { name: Wall, entities: [{}] }
+        ").unwrap()
+    }
+
+    #[test]
+    pub fn parses_version_1_levels()
+    {
+        let levels = parse_levels("
+            levels:
+                -
+                    name: Old
+                    par: 12
+                    tiles:
+                        - = = = = =
+                        - S . P B D
+        ", &test_prefabs());
+
+        let level = &levels[0];
+        assert_eq!(level.meta.par, Some(12));
+        assert_eq!(level.player_pos, vec2(2.0, 0.0));
+        assert_eq!(level.initial_stalker_path, vec![vec2(1, 0)]);
+        let block = level.tiles.iter().find(|t| t.code == "B").unwrap();
+        assert_eq!(block.layer, TileLayer::Objects);
+        assert_eq!(block.pos, vec2(3.0, 0.0));
+    }
+
+    #[test]
+    pub fn parses_stacked_layers_and_metadata()
+    {
+        let levels = parse_levels("
+            version: 2
+            levels:
+                -
+                    name: New
+                    meta: { author: Someone, par: 3, tags: [tutorial] }
+                    terrain:
+                        - = = = = =
+                        - b b . . D
+                    objects:
+                        - . . . . .
+                        - . . . B .
+                    actors:
+                        - . . . . .
+                        - S P . . .
+        ", &test_prefabs());
+
+        let level = &levels[0];
+        assert_eq!(level.meta.author, Some("Someone".to_string()));
+        assert_eq!(level.meta.tags, vec!["tutorial".to_string()]);
+        let on_first_button: Vec<_> = level.tiles.iter().filter(|t| t.pos == vec2(0.0, 0.0)).map(|t| (t.code.as_str(), t.layer)).collect();
+        assert_eq!(on_first_button, vec![("b", TileLayer::Terrain), ("S", TileLayer::Actors)]);
+    }
+
+    #[test]
+    pub fn saved_levels_load_the_same()
+    {
+        let prefabs: PrefabTable = serde_yaml::from_str(include_str!("../../assets/entities.yaml")).unwrap();
+        let levels = parse_levels(include_str!("../../assets/packs/main/levels.yaml"), &prefabs);
+        let saved = parse_levels(&levels_to_yaml(&levels), &prefabs);
+        assert_eq!(saved.len(), levels.len());
+        for (saved, level) in saved.iter().zip(levels.iter())
+        {
+            let mut level = level.clone();
+            level.tiles.sort_by_key(|t| t.layer as u32);
+            assert_eq!(*saved, level);
+        }
+    }
+}
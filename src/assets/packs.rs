@@ -0,0 +1,155 @@
+//! Level packs: directories under `packs/` with a `pack.yaml` manifest
+//! listing the level files that make up the pack, in order.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde_yaml;
+
+use assets::{get_asset_path, read_asset_string, try_load_levels, Level};
+use prefabs::PrefabTable;
+use progress::Progress;
+
+pub const PACKS_FOLDER: &str = "packs";
+pub const MANIFEST_NAME: &str = "pack.yaml";
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct PackManifest
+{
+    pub title: String,
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub order: i32,
+    pub levels: Vec<String>,
+    #[serde(default)]
+    pub unlock: UnlockRules
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct UnlockRules
+{
+    #[serde(default)]
+    pub after: Option<String>,
+    #[serde(default)]
+    pub stars: u32
+}
+
+#[derive(Clone, Debug)]
+pub struct Pack
+{
+    /// The name of the pack's directory, which is how progress and unlock
+    /// rules refer to it.
+    pub id: String,
+    /// Where the pack lives, relative to the assets folder unless it was
+    /// given as an absolute path.
+    pub dir: PathBuf,
+    pub manifest: PackManifest
+}
+
+impl Pack
+{
+    pub fn manifest_path(&self) -> PathBuf
+    {
+        self.dir.join(MANIFEST_NAME)
+    }
+
+    pub fn level_files(&self) -> Vec<PathBuf>
+    {
+        self.manifest.levels.iter().map(|file| self.dir.join(file)).collect()
+    }
+
+    /// Every level in the pack, each with the file it came from.
+    pub fn try_load_levels(&self, prefabs: &PrefabTable) -> Result<Vec<(PathBuf, Level)>, String>
+    {
+        let mut levels = Vec::new();
+        for file in self.level_files()
+        {
+            let loaded = try_load_levels(&file, prefabs)?;
+            levels.extend(loaded.into_iter().map(|level| (file.clone(), level)));
+        }
+        if levels.is_empty()
+        {
+            return Err(format!("No levels in pack '{}'", self.id));
+        }
+        Ok(levels)
+    }
+
+    pub fn is_unlocked(&self, progress: &Progress) -> bool
+    {
+        if progress.unlocked_packs.contains(&self.id)
+        {
+            return true;
+        }
+
+        let rules = &self.manifest.unlock;
+        let after_done = match rules.after
+        {
+            Some(ref pack) => progress.pack_complete(pack),
+            None => true
+        };
+        after_done && progress.total_stars() >= rules.stars
+    }
+}
+
+/// Loads a pack from its directory, or from the manifest inside it.
+pub fn load_pack<P>(path: P) -> Result<Pack, String>
+    where P: AsRef<Path>
+{
+    let mut dir = path.as_ref().to_path_buf();
+    if dir.ends_with(MANIFEST_NAME)
+    {
+        dir.pop();
+    }
+    let id = dir.file_name().map(|name| name.to_string_lossy().into_owned())
+        .ok_or(format!("'{}' isn't a pack directory", dir.display()))?;
+
+    let yaml = read_asset_string(dir.join(MANIFEST_NAME))?;
+    let manifest = serde_yaml::from_str(&yaml).map_err(|e| format!("Failed to parse manifest of pack '{}': {}", id, e))?;
+    Ok(Pack { id: id, dir: dir, manifest: manifest })
+}
+
+/// Every pack in the packs folder, in the order they should be offered.
+pub fn find_packs() -> Result<Vec<Pack>, String>
+{
+    let folder = get_asset_path(PACKS_FOLDER);
+    let entries = fs::read_dir(&folder).map_err(|e| format!("Could not read '{}': {}", folder.display(), e))?;
+
+    let mut packs = Vec::new();
+    for entry in entries.filter_map(|entry| entry.ok())
+    {
+        let path = entry.path();
+        if path.join(MANIFEST_NAME).is_file()
+        {
+            packs.push(load_pack(Path::new(PACKS_FOLDER).join(entry.file_name()))?);
+        }
+    }
+    packs.sort_by(|a, b| (a.manifest.order, &a.manifest.title).cmp(&(b.manifest.order, &b.manifest.title)));
+    Ok(packs)
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use progress::LevelResult;
+
+    #[test]
+    pub fn unlock_rules()
+    {
+        let manifest: PackManifest = serde_yaml::from_str("
+            title: Extra
+            levels: [extra.yaml]
+            unlock: { after: main, stars: 4 }
+        ").unwrap();
+        let pack = Pack { id: "extra".to_string(), dir: PathBuf::from("packs/extra"), manifest: manifest };
+
+        let mut progress = Progress::default();
+        progress.record("main/One", LevelResult::new(10, 0, 0, None));
+        assert!(!pack.is_unlocked(&progress));
+        progress.complete_pack("main");
+        assert!(!pack.is_unlocked(&progress));
+        progress.record("main/Two", LevelResult::new(10, 0, 1, None));
+        assert!(pack.is_unlocked(&progress));
+    }
+}
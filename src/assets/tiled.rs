@@ -0,0 +1,1059 @@
+//! Levels as maps for the Tiled editor (http://www.mapeditor.org), in either
+//! its XML (.tmx) or JSON formats.
+//!
+//! The terrain and objects layers become tile layers, using a tileset over
+//! `atlas.png` where each tile we use carries a `code` property. Actors are
+//! objects in an "actors" object layer with their code as the object type,
+//! and each button/gate link is a polyline in a "links" layer drawn from the
+//! button to the gate. Level metadata lives in the map's properties.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+use image::{self, GenericImage};
+use serde_json::{self, Value};
+use serde_json::Map;
+use serde_yaml;
+use xml::reader::{EventReader, XmlEvent as ReadEvent};
+use xml::writer::{EmitterConfig, XmlEvent as WriteEvent};
+
+use assets::{build_level, level_to_grid, get_asset_bytes, Level, LevelData, LinkData, Objective, TileLayer};
+use prefabs::PrefabTable;
+
+const GID_MASK: u32 = 0x1fff_ffff;
+
+pub struct TiledMap
+{
+    pub width: u32,
+    pub height: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub properties: Vec<Property>,
+    pub tilesets: Vec<Tileset>,
+    pub layers: Vec<MapLayer>
+}
+
+pub struct Property
+{
+    pub name: String,
+    pub kind: String,
+    pub value: String
+}
+
+pub struct Tileset
+{
+    pub first_gid: u32,
+    pub name: String,
+    pub image: String,
+    pub image_width: u32,
+    pub image_height: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub columns: u32,
+    pub tile_count: u32,
+    pub codes: BTreeMap<u32, String>
+}
+
+pub enum MapLayer
+{
+    Tiles { name: String, data: Vec<u32> },
+    Objects { name: String, objects: Vec<MapObject> }
+}
+
+pub struct MapObject
+{
+    pub id: u32,
+    pub name: String,
+    pub kind: String,
+    pub gid: Option<u32>,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub polyline: Vec<(f32, f32)>
+}
+
+/// The image Tiled should show tiles from.
+pub struct AtlasInfo
+{
+    pub image: String,
+    pub columns: u32,
+    pub rows: u32,
+    pub tile_size: u32
+}
+
+
+pub fn load_tiled_level<P>(path: P, prefabs: &PrefabTable) -> Result<Level, String>
+    where P: AsRef<Path>
+{
+    let path = path.as_ref();
+    let mut contents = String::new();
+    File::open(path).and_then(|mut f| f.read_to_string(&mut contents))
+        .map_err(|e| format!("Could not read '{}': {}", path.display(), e))?;
+
+    let map = if is_tmx(path) { map_from_tmx(&contents)? } else { map_from_json(&contents)? };
+    map_to_level(&map, prefabs)
+}
+
+/// Writes a level out for editing in Tiled, as TMX or JSON depending on the
+/// extension of `path`.
+pub fn export_tiled_level<P>(level: &Level, prefabs: &PrefabTable, path: P) -> Result<(), String>
+    where P: AsRef<Path>
+{
+    let path = path.as_ref();
+    let atlas_image = image::load_from_memory(&get_asset_bytes("atlas.png")).map_err(|e| format!("Could not read atlas: {}", e))?;
+    let (width, height) = atlas_image.dimensions();
+    let atlas = AtlasInfo { image: "atlas.png".to_string(), columns: width / 16, rows: height / 16, tile_size: 16 };
+
+    let map = level_to_map(level, prefabs, &atlas);
+    let contents = if is_tmx(path) { map_to_tmx(&map) } else { map_to_json(&map) };
+    File::create(path).and_then(|mut f| f.write_all(contents.as_bytes()))
+        .map_err(|e| format!("Could not write '{}': {}", path.display(), e))
+}
+
+fn is_tmx(path: &Path) -> bool
+{
+    path.extension().is_some_and(|ext| ext == "tmx")
+}
+
+
+pub fn level_to_map(level: &Level, prefabs: &PrefabTable, atlas: &AtlasInfo) -> TiledMap
+{
+    let tile_size = atlas.tile_size;
+    let tile_count = atlas.columns * atlas.rows;
+
+    // Tiles go on the atlas cell their prefab is drawn with where possible,
+    // so Tiled shows the right picture. Anything else gets a spare cell.
+    let mut codes = BTreeMap::new();
+    let mut ids = BTreeMap::new();
+    let mut leftover = Vec::new();
+    let mut all_codes: Vec<&String> = prefabs.prefabs.keys().collect();
+    all_codes.sort();
+    for code in all_codes
+    {
+        let region = prefabs.prefabs[code].entities.iter().rev().filter_map(|e| e.sprite.as_ref()).next().map(|s| s.region);
+        match region
+        {
+            Some([x, y]) if x < atlas.columns && y < atlas.rows && !codes.contains_key(&((atlas.rows - 1 - y) * atlas.columns + x)) =>
+            {
+                let id = (atlas.rows - 1 - y) * atlas.columns + x;
+                codes.insert(id, code.clone());
+                ids.insert(code.clone(), id);
+            },
+            _ => leftover.push(code.clone())
+        }
+    }
+    for code in leftover
+    {
+        let id = (0..tile_count).rev().find(|id| !codes.contains_key(id)).expect("Ran out of tiles for the Tiled tileset");
+        codes.insert(id, code.clone());
+        ids.insert(code, id);
+    }
+
+    let tileset = Tileset
+    {
+        first_gid: 1,
+        name: "atlas".to_string(),
+        image: atlas.image.clone(),
+        image_width: atlas.columns * tile_size,
+        image_height: atlas.rows * tile_size,
+        tile_width: tile_size,
+        tile_height: tile_size,
+        columns: atlas.columns,
+        tile_count: tile_count,
+        codes: codes
+    };
+
+    let mut layers = Vec::new();
+    for layer in &[TileLayer::Terrain, TileLayer::Objects]
+    {
+        let data = level.layer_rows(*layer).iter()
+            .flat_map(|row| row.iter())
+            .map(|code| if code == "." { 0 } else { ids[code] + 1 })
+            .collect();
+        layers.push(MapLayer::Tiles { name: layer.name().to_string(), data: data });
+    }
+
+    let mut next_id = 1;
+    let mut actors = Vec::new();
+    for tile in level.tiles.iter().filter(|t| t.layer == TileLayer::Actors)
+    {
+        let cell = level_to_grid(tile.pos, level.height);
+        let name = prefabs.get(&tile.code).map_or(String::new(), |prefab| prefab.name.clone());
+        actors.push(MapObject
+        {
+            id: next_id,
+            name: name,
+            kind: tile.code.clone(),
+            gid: None,
+            x: (cell[0] * tile_size) as f32,
+            y: (cell[1] * tile_size) as f32,
+            width: tile_size as f32,
+            height: tile_size as f32,
+            polyline: Vec::new()
+        });
+        next_id += 1;
+    }
+    layers.push(MapLayer::Objects { name: "actors".to_string(), objects: actors });
+
+    let mut links = Vec::new();
+    let half = (tile_size / 2) as f32;
+    for link in &level.links
+    {
+        let button = level_to_grid(link.button, level.height);
+        let gate = level_to_grid(link.gate, level.height);
+        let dx = (gate[0] as f32 - button[0] as f32) * tile_size as f32;
+        let dy = (gate[1] as f32 - button[1] as f32) * tile_size as f32;
+        links.push(MapObject
+        {
+            id: next_id,
+            name: String::new(),
+            kind: "link".to_string(),
+            gid: None,
+            x: (button[0] * tile_size) as f32 + half,
+            y: (button[1] * tile_size) as f32 + half,
+            width: 0.0,
+            height: 0.0,
+            polyline: vec![(0.0, 0.0), (dx, dy)]
+        });
+        next_id += 1;
+    }
+    layers.push(MapLayer::Objects { name: "links".to_string(), objects: links });
+
+    let mut properties = vec![string_property("name", &level.name)];
+    {
+        let meta = &level.meta;
+        let optional = [("author", &meta.author), ("hint", &meta.hint), ("music", &meta.music), ("camera", &meta.camera)];
+        for &(name, value) in &optional
+        {
+            if let Some(ref value) = *value
+            {
+                properties.push(string_property(name, value));
+            }
+        }
+        if let Some(par) = meta.par
+        {
+            properties.push(Property { name: "par".to_string(), kind: "int".to_string(), value: par.to_string() });
+        }
+        if !meta.tags.is_empty()
+        {
+            properties.push(string_property("tags", &meta.tags.join(",")));
+        }
+        if !meta.objective.is_exit()
+        {
+            properties.push(string_property("objective", meta.objective.name()));
+        }
+        if !level.collision.is_empty()
+        {
+            let yaml = serde_yaml::to_string(&level.collision).expect("Failed to write collision overrides");
+            properties.push(string_property("collision", &yaml));
+        }
+    }
+
+    TiledMap
+    {
+        width: level.width as u32,
+        height: level.height as u32,
+        tile_width: tile_size,
+        tile_height: tile_size,
+        properties: properties,
+        tilesets: vec![tileset],
+        layers: layers
+    }
+}
+
+fn string_property(name: &str, value: &str) -> Property
+{
+    Property { name: name.to_string(), kind: "string".to_string(), value: value.to_string() }
+}
+
+pub fn map_to_level(map: &TiledMap, prefabs: &PrefabTable) -> Result<Level, String>
+{
+    let (width, height) = (map.width as usize, map.height as usize);
+    let mut codes = BTreeMap::new();
+    for tileset in &map.tilesets
+    {
+        for (id, code) in &tileset.codes
+        {
+            codes.insert(tileset.first_gid + id, code.clone());
+        }
+    }
+    let code_for_gid = |gid: u32| -> Result<Option<String>, String>
+    {
+        let gid = gid & GID_MASK;
+        if gid == 0
+        {
+            return Ok(None);
+        }
+        codes.get(&gid).cloned().map(Some).ok_or_else(|| format!("Tile {} has no 'code' property", gid))
+    };
+
+    let mut grids: BTreeMap<&str, Vec<Vec<String>>> = BTreeMap::new();
+    let mut data = LevelData { name: "Untitled".to_string(), .. Default::default() };
+
+    for layer in &map.layers
+    {
+        match *layer
+        {
+            MapLayer::Tiles { ref name, data: ref tiles } =>
+            {
+                let layer = TileLayer::from_name(name).ok_or_else(|| format!("Unknown tile layer '{}'", name))?;
+                if tiles.len() != width * height
+                {
+                    return Err(format!("Layer '{}' has {} tiles, expected {}", name, tiles.len(), width * height));
+                }
+                let grid = grids.entry(layer.name()).or_insert_with(|| empty_grid(width, height));
+                for (i, gid) in tiles.iter().enumerate()
+                {
+                    if let Some(code) = code_for_gid(*gid)?
+                    {
+                        grid[i / width][i % width] = code;
+                    }
+                }
+            },
+            MapLayer::Objects { ref name, ref objects } if name == "links" =>
+            {
+                for object in objects
+                {
+                    let (first, last) = match (object.polyline.first(), object.polyline.last())
+                    {
+                        (Some(first), Some(last)) if object.polyline.len() >= 2 => (*first, *last),
+                        _ => return Err(format!("Link object {} needs a polyline from a button to a gate", object.id))
+                    };
+                    let cell = |dx: f32, dy: f32| [((object.x + dx) / map.tile_width as f32).floor() as u32, ((object.y + dy) / map.tile_height as f32).floor() as u32];
+                    data.links.push(LinkData { button: cell(first.0, first.1), gate: cell(last.0, last.1) });
+                }
+            },
+            MapLayer::Objects { ref name, ref objects } =>
+            {
+                let layer = TileLayer::from_name(name).ok_or_else(|| format!("Unknown object layer '{}'", name))?;
+                let grid = grids.entry(layer.name()).or_insert_with(|| empty_grid(width, height));
+                for object in objects
+                {
+                    // Tile objects hang up from their bottom edge, while
+                    // everything else hangs down from its top edge.
+                    let (code, centre_y) = match object.gid
+                    {
+                        Some(gid) => (code_for_gid(gid)?, object.y - object.height / 2.0),
+                        None => (Some(object.kind.clone()), object.y + object.height / 2.0)
+                    };
+                    let code = code.ok_or_else(|| format!("Object {} has no tile code", object.id))?;
+                    let column = ((object.x + object.width / 2.0) / map.tile_width as f32).floor();
+                    let row = (centre_y / map.tile_height as f32).floor();
+                    if column < 0.0 || row < 0.0 || column as usize >= width || row as usize >= height
+                    {
+                        return Err(format!("Object {} is outside the map", object.id));
+                    }
+                    grid[row as usize][column as usize] = code;
+                }
+            }
+        }
+    }
+
+    for property in &map.properties
+    {
+        let value = property.value.clone();
+        match property.name.as_str()
+        {
+            "name" => data.name = value,
+            "author" => data.meta.author = Some(value),
+            "hint" => data.meta.hint = Some(value),
+            "music" => data.meta.music = Some(value),
+            "camera" => data.meta.camera = Some(value),
+            "par" => data.meta.par = Some(value.parse().map_err(|_| format!("Bad par '{}'", value))?),
+            "tags" => data.meta.tags = value.split(',').map(|tag| tag.trim().to_string()).filter(|tag| !tag.is_empty()).collect(),
+            "objective" => data.meta.objective = Objective::from_name(&value).ok_or(format!("Unknown objective '{}'", value))?,
+            "collision" => data.collision = serde_yaml::from_str(&value).map_err(|e| format!("Bad collision property: {}", e))?,
+            _ => ()
+        }
+    }
+
+    let rows = |layer: TileLayer| grids.get(layer.name()).map_or(Vec::new(), |grid| grid.iter().map(|row| row.join(" ")).collect());
+    data.terrain = rows(TileLayer::Terrain);
+    data.objects = rows(TileLayer::Objects);
+    data.actors = rows(TileLayer::Actors);
+
+    build_level(data, 2, &BTreeMap::new(), prefabs)
+}
+
+fn empty_grid(width: usize, height: usize) -> Vec<Vec<String>>
+{
+    vec![vec![".".to_string(); width]; height]
+}
+
+
+pub fn map_to_json(map: &TiledMap) -> String
+{
+    let mut layers = Vec::new();
+    for layer in &map.layers
+    {
+        layers.push(match *layer
+        {
+            MapLayer::Tiles { ref name, ref data } => json!({
+                "name": name, "type": "tilelayer", "x": 0, "y": 0, "width": map.width, "height": map.height,
+                "opacity": 1, "visible": true, "data": data
+            }),
+            MapLayer::Objects { ref name, ref objects } =>
+            {
+                let objects: Vec<Value> = objects.iter().map(|o|
+                {
+                    let mut object = json!({
+                        "id": o.id, "name": o.name, "type": o.kind, "x": o.x, "y": o.y,
+                        "width": o.width, "height": o.height, "rotation": 0, "visible": true
+                    });
+                    if let Some(gid) = o.gid
+                    {
+                        object["gid"] = json!(gid);
+                    }
+                    if !o.polyline.is_empty()
+                    {
+                        object["polyline"] = Value::Array(o.polyline.iter().map(|&(x, y)| json!({ "x": x, "y": y })).collect());
+                    }
+                    object
+                }).collect();
+                json!({
+                    "name": name, "type": "objectgroup", "draworder": "topdown", "x": 0, "y": 0,
+                    "opacity": 1, "visible": true, "objects": objects
+                })
+            }
+        });
+    }
+
+    let tilesets: Vec<Value> = map.tilesets.iter().map(|t|
+    {
+        let mut tile_properties = Map::new();
+        let mut tile_property_types = Map::new();
+        for (id, code) in &t.codes
+        {
+            tile_properties.insert(id.to_string(), json!({ "code": code }));
+            tile_property_types.insert(id.to_string(), json!({ "code": "string" }));
+        }
+        json!({
+            "firstgid": t.first_gid, "name": t.name, "image": t.image,
+            "imagewidth": t.image_width, "imageheight": t.image_height,
+            "tilewidth": t.tile_width, "tileheight": t.tile_height,
+            "tilecount": t.tile_count, "columns": t.columns, "margin": 0, "spacing": 0,
+            "tileproperties": tile_properties, "tilepropertytypes": tile_property_types
+        })
+    }).collect();
+
+    let mut properties = Map::new();
+    let mut property_types = Map::new();
+    for property in &map.properties
+    {
+        let value = match property.kind.as_str()
+        {
+            "int" => json!(property.value.parse::<i64>().unwrap_or(0)),
+            _ => json!(property.value)
+        };
+        properties.insert(property.name.clone(), value);
+        property_types.insert(property.name.clone(), json!(property.kind));
+    }
+
+    let next_object_id = map.layers.iter().map(|layer| match *layer
+    {
+        MapLayer::Objects { ref objects, .. } => objects.iter().map(|o| o.id + 1).max().unwrap_or(1),
+        _ => 1
+    }).max().unwrap_or(1);
+
+    let root = json!({
+        "version": 1, "tiledversion": "1.0.3", "orientation": "orthogonal", "renderorder": "right-down",
+        "width": map.width, "height": map.height, "tilewidth": map.tile_width, "tileheight": map.tile_height,
+        "nextobjectid": next_object_id, "properties": properties, "propertytypes": property_types,
+        "layers": layers, "tilesets": tilesets
+    });
+    serde_json::to_string_pretty(&root).expect("Failed to write Tiled JSON")
+}
+
+pub fn map_from_json(json: &str) -> Result<TiledMap, String>
+{
+    let root: Value = serde_json::from_str(json).map_err(|e| format!("Failed to parse Tiled JSON: {}", e))?;
+
+    let mut tilesets = Vec::new();
+    for tileset in json_array(&root, "tilesets")?
+    {
+        if tileset.get("source").is_some()
+        {
+            return Err("External tilesets aren't supported, embed the tileset in the map".to_string());
+        }
+
+        let mut codes = BTreeMap::new();
+        // Older versions of Tiled keep per-tile properties in a map keyed by
+        // tile id, newer ones in a list of tiles.
+        if let Some(tile_properties) = tileset.get("tileproperties").and_then(Value::as_object)
+        {
+            for (id, properties) in tile_properties
+            {
+                if let Some(code) = json_properties(properties)?.into_iter().find(|p| p.name == "code")
+                {
+                    codes.insert(id.parse().map_err(|_| format!("Bad tile id '{}'", id))?, code.value);
+                }
+            }
+        }
+        if let Some(tiles) = tileset.get("tiles").and_then(Value::as_array)
+        {
+            for tile in tiles
+            {
+                let properties = tile.get("properties").map_or(Ok(Vec::new()), json_properties)?;
+                if let Some(code) = properties.into_iter().find(|p| p.name == "code")
+                {
+                    codes.insert(json_u32(tile, "id")?, code.value);
+                }
+            }
+        }
+
+        tilesets.push(Tileset
+        {
+            first_gid: json_u32(tileset, "firstgid")?,
+            name: json_string(tileset, "name"),
+            image: json_string(tileset, "image"),
+            image_width: json_u32(tileset, "imagewidth").unwrap_or(0),
+            image_height: json_u32(tileset, "imageheight").unwrap_or(0),
+            tile_width: json_u32(tileset, "tilewidth")?,
+            tile_height: json_u32(tileset, "tileheight")?,
+            columns: json_u32(tileset, "columns").unwrap_or(0),
+            tile_count: json_u32(tileset, "tilecount").unwrap_or(0),
+            codes: codes
+        });
+    }
+
+    let mut layers = Vec::new();
+    for layer in json_array(&root, "layers")?
+    {
+        let name = json_string(layer, "name");
+        match layer.get("type").and_then(Value::as_str)
+        {
+            Some("tilelayer") =>
+            {
+                if layer.get("encoding").and_then(Value::as_str).is_some_and(|e| e != "csv")
+                {
+                    return Err(format!("Layer '{}' is compressed, save it with the CSV layer format", name));
+                }
+                let data = json_array(layer, "data")?.iter()
+                    .map(|gid| gid.as_u64().map(|gid| gid as u32).ok_or_else(|| format!("Bad tile in layer '{}'", name)))
+                    .collect::<Result<Vec<u32>, String>>()?;
+                layers.push(MapLayer::Tiles { name: name, data: data });
+            },
+            Some("objectgroup") =>
+            {
+                let mut objects = Vec::new();
+                for object in json_array(layer, "objects")?
+                {
+                    let mut polyline = Vec::new();
+                    if let Some(points) = object.get("polyline").and_then(Value::as_array)
+                    {
+                        for point in points
+                        {
+                            polyline.push((json_f32(point, "x")?, json_f32(point, "y")?));
+                        }
+                    }
+                    // Tiled 1.9 renamed an object's type to its class.
+                    let kind = match object.get("type").and_then(Value::as_str)
+                    {
+                        Some(kind) if !kind.is_empty() => kind.to_string(),
+                        _ => json_string(object, "class")
+                    };
+                    objects.push(MapObject
+                    {
+                        id: json_u32(object, "id")?,
+                        name: json_string(object, "name"),
+                        kind: kind,
+                        gid: object.get("gid").and_then(Value::as_u64).map(|gid| gid as u32),
+                        x: json_f32(object, "x")?,
+                        y: json_f32(object, "y")?,
+                        width: json_f32(object, "width").unwrap_or(0.0),
+                        height: json_f32(object, "height").unwrap_or(0.0),
+                        polyline: polyline
+                    });
+                }
+                layers.push(MapLayer::Objects { name: name, objects: objects });
+            },
+            _ => ()
+        }
+    }
+
+    Ok(TiledMap
+    {
+        width: json_u32(&root, "width")?,
+        height: json_u32(&root, "height")?,
+        tile_width: json_u32(&root, "tilewidth")?,
+        tile_height: json_u32(&root, "tileheight")?,
+        properties: root.get("properties").map_or(Ok(Vec::new()), json_properties)?,
+        tilesets: tilesets,
+        layers: layers
+    })
+}
+
+/// Properties are either a name => value map (Tiled 1.0) or a list of
+/// { name, type, value } objects (Tiled 1.2 onwards).
+fn json_properties(properties: &Value) -> Result<Vec<Property>, String>
+{
+    let value_string = |value: &Value| match *value
+    {
+        Value::String(ref s) => s.clone(),
+        ref other => other.to_string()
+    };
+
+    match *properties
+    {
+        Value::Object(ref map) => Ok(map.iter().map(|(name, value)| Property
+        {
+            name: name.clone(),
+            kind: if value.is_string() { "string".to_string() } else { "int".to_string() },
+            value: value_string(value)
+        }).collect()),
+        Value::Array(ref list) => list.iter().map(|property| Ok(Property
+        {
+            name: json_string(property, "name"),
+            kind: json_string(property, "type"),
+            value: property.get("value").map_or(String::new(), &value_string)
+        })).collect(),
+        _ => Err("Bad properties".to_string())
+    }
+}
+
+fn json_array<'a>(value: &'a Value, key: &str) -> Result<&'a Vec<Value>, String>
+{
+    value.get(key).and_then(Value::as_array).ok_or_else(|| format!("Missing list '{}'", key))
+}
+
+fn json_u32(value: &Value, key: &str) -> Result<u32, String>
+{
+    value.get(key).and_then(Value::as_u64).map(|v| v as u32).ok_or_else(|| format!("Missing number '{}'", key))
+}
+
+fn json_f32(value: &Value, key: &str) -> Result<f32, String>
+{
+    value.get(key).and_then(Value::as_f64).map(|v| v as f32).ok_or_else(|| format!("Missing number '{}'", key))
+}
+
+fn json_string(value: &Value, key: &str) -> String
+{
+    value.get(key).and_then(Value::as_str).unwrap_or("").to_string()
+}
+
+
+/// Just enough of an XML document tree to read and write TMX files.
+struct Element
+{
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String
+}
+
+impl Element
+{
+    fn new(name: &str) -> Self
+    {
+        Element { name: name.to_string(), attributes: Vec::new(), children: Vec::new(), text: String::new() }
+    }
+
+    fn attr<T: ToString>(mut self, name: &str, value: T) -> Self
+    {
+        self.attributes.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    fn child(mut self, child: Element) -> Self
+    {
+        self.children.push(child);
+        self
+    }
+
+    fn get(&self, name: &str) -> Option<&str>
+    {
+        self.attributes.iter().find(|a| a.0 == name).map(|a| a.1.as_str())
+    }
+
+    fn get_u32(&self, name: &str) -> Result<u32, String>
+    {
+        self.get(name).and_then(|v| v.parse().ok()).ok_or_else(|| format!("Missing number '{}' on <{}>", name, self.name))
+    }
+
+    fn get_f32(&self, name: &str) -> Result<f32, String>
+    {
+        self.get(name).and_then(|v| v.parse().ok()).ok_or_else(|| format!("Missing number '{}' on <{}>", name, self.name))
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item=&'a Element> + 'a
+    {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    fn write<W: Write>(&self, writer: &mut ::xml::writer::EventWriter<W>) -> ::xml::writer::Result<()>
+    {
+        let mut start = WriteEvent::start_element(self.name.as_str());
+        for (name, value) in &self.attributes
+        {
+            start = start.attr(name.as_str(), value);
+        }
+        writer.write(start)?;
+        if !self.text.is_empty()
+        {
+            writer.write(WriteEvent::characters(&self.text))?;
+        }
+        for child in &self.children
+        {
+            child.write(writer)?;
+        }
+        writer.write(WriteEvent::end_element())
+    }
+}
+
+fn tmx_properties(properties: &[Property]) -> Element
+{
+    let mut element = Element::new("properties");
+    for property in properties
+    {
+        let mut child = Element::new("property").attr("name", &property.name);
+        if property.kind != "string"
+        {
+            child = child.attr("type", &property.kind);
+        }
+        // Tiled keeps multi-line values as text, since attribute values lose
+        // their line breaks.
+        if property.value.contains('\n')
+        {
+            child.text = property.value.clone();
+        }
+        else
+        {
+            child = child.attr("value", &property.value);
+        }
+        element = element.child(child);
+    }
+    element
+}
+
+fn read_tmx_properties(element: &Element) -> Vec<Property>
+{
+    element.children("properties").flat_map(|p| p.children("property")).map(|property| Property
+    {
+        name: property.get("name").unwrap_or("").to_string(),
+        kind: property.get("type").unwrap_or("string").to_string(),
+        value: property.get("value").map_or(property.text.clone(), |v| v.to_string())
+    }).collect()
+}
+
+pub fn map_to_tmx(map: &TiledMap) -> String
+{
+    let mut root = Element::new("map")
+        .attr("version", "1.0")
+        .attr("orientation", "orthogonal")
+        .attr("renderorder", "right-down")
+        .attr("width", map.width)
+        .attr("height", map.height)
+        .attr("tilewidth", map.tile_width)
+        .attr("tileheight", map.tile_height);
+    root = root.child(tmx_properties(&map.properties));
+
+    let mut next_object_id = 1;
+    for tileset in &map.tilesets
+    {
+        let mut element = Element::new("tileset")
+            .attr("firstgid", tileset.first_gid)
+            .attr("name", &tileset.name)
+            .attr("tilewidth", tileset.tile_width)
+            .attr("tileheight", tileset.tile_height)
+            .attr("tilecount", tileset.tile_count)
+            .attr("columns", tileset.columns)
+            .child(Element::new("image").attr("source", &tileset.image).attr("width", tileset.image_width).attr("height", tileset.image_height));
+        for (id, code) in &tileset.codes
+        {
+            let properties = [string_property("code", code)];
+            element = element.child(Element::new("tile").attr("id", id).child(tmx_properties(&properties)));
+        }
+        root = root.child(element);
+    }
+
+    for layer in &map.layers
+    {
+        root = root.child(match *layer
+        {
+            MapLayer::Tiles { ref name, ref data } =>
+            {
+                let mut csv = String::from("\n");
+                for row in data.chunks(map.width as usize)
+                {
+                    let row: Vec<String> = row.iter().map(|gid| gid.to_string()).collect();
+                    csv.push_str(&row.join(","));
+                    csv.push_str(",\n");
+                }
+                let len = csv.len();
+                csv.truncate(len - 2);
+                csv.push('\n');
+
+                let mut data = Element::new("data").attr("encoding", "csv");
+                data.text = csv;
+                Element::new("layer").attr("name", name).attr("width", map.width).attr("height", map.height).child(data)
+            },
+            MapLayer::Objects { ref name, ref objects } =>
+            {
+                let mut group = Element::new("objectgroup").attr("name", name);
+                for o in objects
+                {
+                    next_object_id = next_object_id.max(o.id + 1);
+                    let mut object = Element::new("object").attr("id", o.id);
+                    if !o.name.is_empty()
+                    {
+                        object = object.attr("name", &o.name);
+                    }
+                    object = object.attr("type", &o.kind);
+                    if let Some(gid) = o.gid
+                    {
+                        object = object.attr("gid", gid);
+                    }
+                    object = object.attr("x", o.x).attr("y", o.y);
+                    if o.width != 0.0 || o.height != 0.0
+                    {
+                        object = object.attr("width", o.width).attr("height", o.height);
+                    }
+                    if !o.polyline.is_empty()
+                    {
+                        let points: Vec<String> = o.polyline.iter().map(|&(x, y)| format!("{},{}", x, y)).collect();
+                        object = object.child(Element::new("polyline").attr("points", points.join(" ")));
+                    }
+                    group = group.child(object);
+                }
+                group
+            }
+        });
+    }
+    root = root.attr("nextobjectid", next_object_id);
+
+    let mut buffer = Vec::new();
+    {
+        let mut writer = EmitterConfig::new().perform_indent(true).create_writer(&mut buffer);
+        root.write(&mut writer).expect("Failed to write TMX");
+    }
+    String::from_utf8(buffer).expect("TMX should be UTF-8")
+}
+
+pub fn map_from_tmx(tmx: &str) -> Result<TiledMap, String>
+{
+    let root = parse_xml(tmx)?;
+    if root.name != "map"
+    {
+        return Err(format!("Expected <map>, found <{}>", root.name));
+    }
+
+    let mut tilesets = Vec::new();
+    for tileset in root.children("tileset")
+    {
+        if tileset.get("source").is_some()
+        {
+            return Err("External tilesets aren't supported, embed the tileset in the map".to_string());
+        }
+        let image = tileset.children("image").next();
+        let mut codes = BTreeMap::new();
+        for tile in tileset.children("tile")
+        {
+            if let Some(code) = read_tmx_properties(tile).into_iter().find(|p| p.name == "code")
+            {
+                codes.insert(tile.get_u32("id")?, code.value);
+            }
+        }
+        tilesets.push(Tileset
+        {
+            first_gid: tileset.get_u32("firstgid")?,
+            name: tileset.get("name").unwrap_or("").to_string(),
+            image: image.and_then(|i| i.get("source")).unwrap_or("").to_string(),
+            image_width: image.and_then(|i| i.get_u32("width").ok()).unwrap_or(0),
+            image_height: image.and_then(|i| i.get_u32("height").ok()).unwrap_or(0),
+            tile_width: tileset.get_u32("tilewidth")?,
+            tile_height: tileset.get_u32("tileheight")?,
+            columns: tileset.get_u32("columns").unwrap_or(0),
+            tile_count: tileset.get_u32("tilecount").unwrap_or(0),
+            codes: codes
+        });
+    }
+
+    let mut layers = Vec::new();
+    for element in &root.children
+    {
+        let name = element.get("name").unwrap_or("").to_string();
+        match element.name.as_str()
+        {
+            "layer" =>
+            {
+                let data = element.children("data").next().ok_or_else(|| format!("Layer '{}' has no data", name))?;
+                if data.get("encoding") != Some("csv")
+                {
+                    return Err(format!("Layer '{}' isn't CSV, save it with the CSV layer format", name));
+                }
+                let tiles = data.text.split(',')
+                    .map(|gid| gid.trim().parse().map_err(|_| format!("Bad tile '{}' in layer '{}'", gid.trim(), name)))
+                    .collect::<Result<Vec<u32>, String>>()?;
+                layers.push(MapLayer::Tiles { name: name, data: tiles });
+            },
+            "objectgroup" =>
+            {
+                let mut objects = Vec::new();
+                for object in element.children("object")
+                {
+                    let mut polyline = Vec::new();
+                    if let Some(points) = object.children("polyline").next().and_then(|p| p.get("points"))
+                    {
+                        for point in points.split_whitespace()
+                        {
+                            let mut xy = point.split(',').map(|v| v.parse::<f32>());
+                            match (xy.next(), xy.next())
+                            {
+                                (Some(Ok(x)), Some(Ok(y))) => polyline.push((x, y)),
+                                _ => return Err(format!("Bad polyline point '{}'", point))
+                            }
+                        }
+                    }
+                    objects.push(MapObject
+                    {
+                        id: object.get_u32("id")?,
+                        name: object.get("name").unwrap_or("").to_string(),
+                        kind: object.get("type").or_else(|| object.get("class")).unwrap_or("").to_string(),
+                        gid: object.get_u32("gid").ok(),
+                        x: object.get_f32("x")?,
+                        y: object.get_f32("y")?,
+                        width: object.get_f32("width").unwrap_or(0.0),
+                        height: object.get_f32("height").unwrap_or(0.0),
+                        polyline: polyline
+                    });
+                }
+                layers.push(MapLayer::Objects { name: name, objects: objects });
+            },
+            _ => ()
+        }
+    }
+
+    Ok(TiledMap
+    {
+        width: root.get_u32("width")?,
+        height: root.get_u32("height")?,
+        tile_width: root.get_u32("tilewidth")?,
+        tile_height: root.get_u32("tileheight")?,
+        properties: read_tmx_properties(&root),
+        tilesets: tilesets,
+        layers: layers
+    })
+}
+
+fn parse_xml(xml: &str) -> Result<Element, String>
+{
+    let mut stack: Vec<Element> = Vec::new();
+    for event in EventReader::from_str(xml)
+    {
+        match event.map_err(|e| format!("Failed to parse TMX: {}", e))?
+        {
+            ReadEvent::StartElement { name, attributes, .. } =>
+            {
+                let mut element = Element::new(&name.local_name);
+                for attribute in attributes
+                {
+                    element.attributes.push((attribute.name.local_name, attribute.value));
+                }
+                stack.push(element);
+            },
+            ReadEvent::Characters(text) | ReadEvent::CData(text) =>
+            {
+                if let Some(element) = stack.last_mut()
+                {
+                    element.text.push_str(&text);
+                }
+            },
+            ReadEvent::EndElement { .. } =>
+            {
+                let element = stack.pop().expect("Unbalanced XML");
+                match stack.last_mut()
+                {
+                    Some(parent) => parent.children.push(element),
+                    None => return Ok(element)
+                }
+            },
+            _ => ()
+        }
+    }
+    Err("TMX file has no root element".to_string())
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use assets::parse_levels;
+    use vectors::*;
+
+    fn prefabs() -> PrefabTable
+    {
+        serde_yaml::from_str(include_str!("../../assets/entities.yaml")).unwrap()
+    }
+
+    fn atlas() -> AtlasInfo
+    {
+        AtlasInfo { image: "atlas.png".to_string(), columns: 8, rows: 8, tile_size: 16 }
+    }
+
+    fn sorted(mut level: Level) -> Level
+    {
+        level.tiles.sort_by(|a, b| (a.layer as u32, a.pos.round_i32().components, &a.code).cmp(&(b.layer as u32, b.pos.round_i32().components, &b.code)));
+        level
+    }
+
+    fn round_trip(level: &Level, prefabs: &PrefabTable)
+    {
+        let map = level_to_map(level, prefabs, &atlas());
+
+        let from_json = map_to_level(&map_from_json(&map_to_json(&map)).unwrap(), prefabs).unwrap();
+        assert_eq!(sorted(from_json), sorted(level.clone()));
+
+        let from_tmx = map_to_level(&map_from_tmx(&map_to_tmx(&map)).unwrap(), prefabs).unwrap();
+        assert_eq!(sorted(from_tmx), sorted(level.clone()));
+    }
+
+    #[test]
+    pub fn bundled_levels_round_trip()
+    {
+        let prefabs = prefabs();
+        for level in parse_levels(include_str!("../../assets/packs/main/levels.yaml"), &prefabs)
+        {
+            round_trip(&level, &prefabs);
+        }
+    }
+
+    #[test]
+    pub fn layers_links_and_metadata_round_trip()
+    {
+        let prefabs = prefabs();
+        let levels = parse_levels("
+            version: 2
+            collision:
+                B: { pushed_by: [stalker] }
+            levels:
+                -
+                    name: Everything
+                    meta:
+                        author: Someone
+                        par: 7
+                        hint: \"Two lines\\nof hint\"
+                        camera: follow
+                        tags: [test, links]
+                    links:
+                        - { button: [1, 1], gate: [3, 1] }
+                    terrain:
+                        - = = = = = =
+                        - = b . G D =
+                        - = b . . . =
+                        - = = = = = =
+                    objects:
+                        - . . . . . .
+                        - . B . . . .
+                        - . . * . . .
+                    actors:
+                        - . . . . . .
+                        - . . . . . .
+                        - . S . . P .
+        ", &prefabs);
+        round_trip(&levels[0], &prefabs);
+        assert_eq!(levels[0].links_to(vec2(3.0, 2.0)), vec![vec2(1, 2)]);
+    }
+}
@@ -0,0 +1,94 @@
+//! Notices when asset files change on disk by polling their modification
+//! times, so they can be reloaded while the game is running.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+pub struct FileWatcher
+{
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    interval: Duration,
+    last_poll: Instant
+}
+
+impl FileWatcher
+{
+    /// A watcher that looks at its files at most once every `interval`.
+    pub fn new(interval: Duration) -> Self
+    {
+        FileWatcher { files: Vec::new(), interval: interval, last_poll: Instant::now() }
+    }
+
+    pub fn watch<P>(&mut self, path: P)
+        where P: AsRef<Path>
+    {
+        let path = path.as_ref().to_path_buf();
+        if !self.files.iter().any(|watched| watched.0 == path)
+        {
+            let modified = modified_time(&path);
+            self.files.push((path, modified));
+        }
+    }
+
+    /// The files that have changed since they were last looked at. Files
+    /// that disappear (as some editors do while saving) count as changed
+    /// once they're back.
+    pub fn poll(&mut self) -> Vec<PathBuf>
+    {
+        if self.last_poll.elapsed() < self.interval
+        {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = Vec::new();
+        for &mut (ref path, ref mut last_modified) in &mut self.files
+        {
+            let modified = modified_time(path);
+            if modified.is_some() && modified != *last_modified
+            {
+                changed.push(path.clone());
+            }
+            if modified.is_some()
+            {
+                *last_modified = modified;
+            }
+        }
+        changed
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime>
+{
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::env;
+    use std::fs::File;
+    use std::io::prelude::*;
+
+    #[test]
+    pub fn notices_changed_files()
+    {
+        let path = env::temp_dir().join(format!("smallworld-watch-{}.txt", ::std::process::id()));
+        File::create(&path).unwrap().write_all(b"one").unwrap();
+
+        let mut watcher = FileWatcher::new(Duration::from_secs(0));
+        watcher.watch(&path);
+        assert!(watcher.poll().is_empty());
+
+        let later = SystemTime::now() + Duration::from_secs(5);
+        File::create(&path).unwrap().set_modified(later).unwrap();
+        assert_eq!(watcher.poll(), vec![path.clone()]);
+        assert!(watcher.poll().is_empty());
+
+        fs::remove_file(&path).unwrap();
+        assert!(watcher.poll().is_empty());
+    }
+}
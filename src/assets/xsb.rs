@@ -0,0 +1,324 @@
+//! Sokoban levels in the usual XSB text format, one or more to a file:
+//!
+//!   `#` wall, `$` block, `.` target, `*` block on a target,
+//!   `@` player, `+` player on a target, and ` `, `-` or `_` for floor.
+//!
+//! Targets become buttons and the level is won once every one of them has a
+//! block on it. Anything the player can't reach is filled in with wall. A
+//! stalker can be added on the longest clear line away from the player, for
+//! levels that want to be played this game's way.
+//!
+//! Lines that aren't part of a map are read as titles: a `Title:` line after
+//! a map names it, otherwise the last such line before it does.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+use assets::{build_level, Level, LevelData, LevelMeta, Objective};
+use prefabs::PrefabTable;
+
+const CODES: [&str; 6] = ["=", ";", "b", "B", "P", "S"];
+
+pub fn load_xsb_levels<P>(path: P, with_stalker: bool, prefabs: &PrefabTable) -> Result<Vec<Level>, String>
+    where P: AsRef<Path>
+{
+    let path = path.as_ref();
+    let mut contents = String::new();
+    File::open(path).and_then(|mut f| f.read_to_string(&mut contents))
+        .map_err(|e| format!("Could not read '{}': {}", path.display(), e))?;
+
+    let pack_name = path.file_stem().map_or("Sokoban".to_string(), |stem| stem.to_string_lossy().into_owned());
+    parse_xsb(&contents, &pack_name, with_stalker, prefabs)
+}
+
+pub fn parse_xsb(text: &str, pack_name: &str, with_stalker: bool, prefabs: &PrefabTable) -> Result<Vec<Level>, String>
+{
+    if let Some(code) = CODES.iter().find(|code| prefabs.get(code).is_none())
+    {
+        return Err(format!("No prefab for tile code '{}'", code));
+    }
+
+    let mut puzzles: Vec<Puzzle> = Vec::new();
+    let mut rows: Vec<&str> = Vec::new();
+    let mut caption: Option<String> = None;
+
+    for line in text.lines().map(|line| line.trim_end())
+    {
+        if is_map_row(line)
+        {
+            rows.push(line);
+            continue;
+        }
+
+        if !rows.is_empty()
+        {
+            puzzles.push(Puzzle { title: caption.take(), author: None, rows: rows });
+            rows = Vec::new();
+        }
+
+        let line = line.trim();
+        if line.is_empty()
+        {
+            continue;
+        }
+        match (line.find(':'), puzzles.last_mut())
+        {
+            (Some(i), Some(puzzle)) if line[..i].eq_ignore_ascii_case("title") => puzzle.title = Some(line[i + 1..].trim().to_string()),
+            (Some(i), Some(puzzle)) if line[..i].eq_ignore_ascii_case("author") => puzzle.author = Some(line[i + 1..].trim().to_string()),
+            _ => caption = Some(line.trim_start_matches(';').trim().to_string())
+        }
+    }
+    if !rows.is_empty()
+    {
+        puzzles.push(Puzzle { title: caption.take(), author: None, rows: rows });
+    }
+
+    puzzles.iter().enumerate().map(|(i, puzzle)|
+    {
+        let name = match puzzle.title
+        {
+            Some(ref title) if !title.is_empty() => title.clone(),
+            _ => format!("{} {}", pack_name, i + 1)
+        };
+        puzzle_to_level_data(puzzle, name, with_stalker)
+            .and_then(|data| build_level(data, 2, &BTreeMap::new(), prefabs))
+    }).collect()
+}
+
+struct Puzzle<'a>
+{
+    title: Option<String>,
+    author: Option<String>,
+    rows: Vec<&'a str>
+}
+
+fn is_map_row(line: &str) -> bool
+{
+    line.contains('#') && line.chars().all(|c| "#$.*@+ -_".contains(c))
+}
+
+fn puzzle_to_level_data(puzzle: &Puzzle, name: String, with_stalker: bool) -> Result<LevelData, String>
+{
+    // One cell of wall all the way round, so the edges of the map look like
+    // the rest of the walls.
+    let width = puzzle.rows.iter().map(|row| row.len()).max().unwrap_or(0) + 2;
+    let height = puzzle.rows.len() + 2;
+    let mut cells = vec![vec![' '; width]; height];
+    for (y, row) in puzzle.rows.iter().enumerate()
+    {
+        for (x, c) in row.chars().enumerate()
+        {
+            cells[y + 1][x + 1] = c;
+        }
+    }
+
+    let mut players = Vec::new();
+    for (y, row) in cells.iter().enumerate()
+    {
+        for (x, &c) in row.iter().enumerate()
+        {
+            if c == '@' || c == '+'
+            {
+                players.push((x, y));
+            }
+        }
+    }
+    if players.len() != 1
+    {
+        return Err(format!("'{}' should have one player, but has {}", name, players.len()));
+    }
+    let player = players[0];
+
+    let inside = reachable_cells(&cells, player).ok_or(format!("'{}' isn't walled in", name))?;
+    let is_inside = |x: usize, y: usize| inside[y][x];
+    let has_block = |x: usize, y: usize| cells[y][x] == '$' || cells[y][x] == '*';
+    let is_target = |x: usize, y: usize| cells[y][x] == '.' || cells[y][x] == '*' || cells[y][x] == '+';
+
+    let count = |test: &dyn Fn(usize, usize) -> bool| (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).filter(|&(x, y)| is_inside(x, y) && test(x, y)).count();
+    let (blocks, targets) = (count(&has_block), count(&is_target));
+    if targets == 0 || blocks < targets
+    {
+        return Err(format!("'{}' has {} blocks for {} targets", name, blocks, targets));
+    }
+
+    let mut stalker = None;
+    if with_stalker
+    {
+        stalker = stalker_cell(player, |x, y| is_inside(x, y) && !has_block(x, y));
+    }
+
+    let mut data = LevelData
+    {
+        name: name,
+        meta: LevelMeta { author: puzzle.author.clone(), objective: Objective::Buttons, tags: vec!["sokoban".to_string()], .. Default::default() },
+        .. Default::default()
+    };
+    for y in 0..height
+    {
+        let mut terrain = Vec::new();
+        let mut objects = Vec::new();
+        let mut actors = Vec::new();
+        for x in 0..width
+        {
+            if !is_inside(x, y)
+            {
+                let above_floor = y + 1 < height && is_inside(x, y + 1);
+                terrain.push(if above_floor { ";" } else { "=" });
+                objects.push(".");
+                actors.push(".");
+                continue;
+            }
+            terrain.push(if is_target(x, y) { "b" } else { "." });
+            objects.push(if has_block(x, y) { "B" } else { "." });
+            actors.push(if (x, y) == player { "P" } else if Some((x, y)) == stalker { "S" } else { "." });
+        }
+        data.terrain.push(terrain.join(" "));
+        data.objects.push(objects.join(" "));
+        data.actors.push(actors.join(" "));
+    }
+    Ok(data)
+}
+
+/// Every cell the player could walk to if none of the blocks were there, or
+/// None if they could walk right off the edge of the map.
+fn reachable_cells(cells: &[Vec<char>], start: (usize, usize)) -> Option<Vec<Vec<bool>>>
+{
+    let (width, height) = (cells[0].len(), cells.len());
+    let mut reached = vec![vec![false; width]; height];
+    let mut open = vec![start];
+    while let Some((x, y)) = open.pop()
+    {
+        if reached[y][x] || cells[y][x] == '#'
+        {
+            continue;
+        }
+        if x == 0 || y == 0 || x == width - 1 || y == height - 1
+        {
+            return None;
+        }
+        reached[y][x] = true;
+        open.extend_from_slice(&[(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]);
+    }
+    Some(reached)
+}
+
+/// The furthest cell from the player along a clear straight line, as long as
+/// it's at least two cells away so the player has a head start.
+fn stalker_cell<F>(player: (usize, usize), clear: F) -> Option<(usize, usize)>
+    where F: Fn(usize, usize) -> bool
+{
+    let directions: [(isize, isize); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+    let mut best = None;
+    let mut best_distance = 1;
+    for &(dx, dy) in &directions
+    {
+        let mut distance = 0;
+        let mut cell = player;
+        loop
+        {
+            let next = ((cell.0 as isize + dx) as usize, (cell.1 as isize + dy) as usize);
+            if !clear(next.0, next.1)
+            {
+                break;
+            }
+            cell = next;
+            distance += 1;
+        }
+        if distance > best_distance
+        {
+            best = Some(cell);
+            best_distance = distance;
+        }
+    }
+    best
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use serde_yaml;
+    use specs::{Planner, Join, Gate};
+
+    use assets::TileLayer;
+    use game_state::{build_world, step_world};
+    use systems::{victory, Motion};
+    use vectors::*;
+
+    fn prefabs() -> PrefabTable
+    {
+        serde_yaml::from_str(include_str!("../../assets/entities.yaml")).unwrap()
+    }
+
+    const PACK: &str = "
+; First
+######
+#.  .#
+# $$ #
+#@   #
+######
+Author: Someone
+
+; ignored
+#####
+#+$ #
+#####
+Title: Second
+";
+
+    #[test]
+    pub fn parses_packs()
+    {
+        let levels = parse_xsb(PACK, "Pack", true, &prefabs()).unwrap();
+        assert_eq!(levels.len(), 2);
+
+        let first = &levels[0];
+        assert_eq!(first.name, "First");
+        assert_eq!(first.meta.author, Some("Someone".to_string()));
+        assert_eq!(first.meta.objective, Objective::Buttons);
+        assert_eq!(first.layer_rows(TileLayer::Terrain)[2], vec!["=", "=", "b", ".", ".", "b", "=", "="]);
+        assert_eq!(first.layer_rows(TileLayer::Objects)[3], vec![".", ".", ".", "B", "B", ".", ".", "."]);
+        assert_eq!(first.stalker_pos, Some(vec2(5.0, 2.0)));
+        assert_eq!(first.initial_stalker_path, vec![vec2(4, 2), vec2(3, 2)]);
+
+        let second = &levels[1];
+        assert_eq!(second.name, "Second");
+        assert_eq!(second.stalker_pos, None);
+        assert_eq!(second.layer_rows(TileLayer::Terrain)[1], vec!["=", "=", ";", ";", ";", "=", "="]);
+    }
+
+    #[test]
+    pub fn solves_imported_puzzle()
+    {
+        let prefabs = prefabs();
+        let level = &parse_xsb(PACK, "Pack", false, &prefabs).unwrap()[0];
+        let mut planner = Planner::new(build_world(level, &prefabs));
+        assert!(!victory::determine_victory_from_buttons(planner.mut_world()));
+
+        for step in "RRULURDDLLU".chars()
+        {
+            let dir = match step
+            {
+                'L' => vec2(-1.0, 0.0),
+                'R' => vec2(1.0, 0.0),
+                'U' => vec2(0.0, 1.0),
+                _ => vec2(0.0, -1.0)
+            };
+            step_world(&mut planner, dir, 0.05);
+            for _ in 0..100
+            {
+                let moving = (&planner.mut_world().read::<Motion>().pass()).join().any(|motion| motion.destination.is_some());
+                if !moving
+                {
+                    break;
+                }
+                step_world(&mut planner, vec2(0.0, 0.0), 0.05);
+            }
+        }
+
+        assert!(victory::determine_victory_from_buttons(planner.mut_world()));
+    }
+}
@@ -0,0 +1,56 @@
+extern crate smallworld;
+
+use std::env;
+use std::path::PathBuf;
+use std::process::exit;
+
+use smallworld::assets::{self, tiled};
+use smallworld::prefabs;
+
+const USAGE: &str = "\
+Converts levels to and from maps for the Tiled editor.
+
+Usage:
+    tiled export <level file in assets> <output dir> [tmx|json]
+    tiled import <output level file> <map.tmx|map.json>...";
+
+fn main()
+{
+    let args: Vec<String> = env::args().skip(1).collect();
+    let prefabs = prefabs::load_prefabs("entities.yaml");
+
+    let result = match args.first().map(|arg| arg.as_str())
+    {
+        Some("export") if args.len() >= 3 =>
+        {
+            let format = args.get(3).map_or("tmx", |arg| arg.as_str());
+            let levels = assets::load_levels(&args[1], &prefabs);
+            levels.iter().enumerate().map(|(i, level)|
+            {
+                let file_name: String = level.name.chars().map(|c| if c.is_alphanumeric() { c.to_ascii_lowercase() } else { '_' }).collect();
+                let mut path = PathBuf::from(&args[2]);
+                path.push(format!("{:02}-{}.{}", i + 1, file_name, format));
+                println!("{}", path.display());
+                tiled::export_tiled_level(level, &prefabs, path)
+            }).collect::<Result<Vec<()>, String>>().map(|_| ())
+        },
+        Some("import") if args.len() >= 3 =>
+        {
+            args[2..].iter()
+                .map(|path| tiled::load_tiled_level(path, &prefabs))
+                .collect::<Result<Vec<_>, String>>()
+                .and_then(|levels| assets::save_levels(&levels, &args[1]).map_err(|e| e.to_string()))
+        },
+        _ =>
+        {
+            println!("{}", USAGE);
+            Ok(())
+        }
+    };
+
+    if let Err(error) = result
+    {
+        println!("{}", error);
+        exit(1);
+    }
+}
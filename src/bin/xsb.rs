@@ -0,0 +1,39 @@
+extern crate smallworld;
+
+use std::env;
+use std::process::exit;
+
+use smallworld::assets::{self, xsb};
+use smallworld::prefabs;
+
+const USAGE: &str = "\
+Converts a pack of Sokoban levels to a level file.
+
+Usage:
+    xsb <pack.xsb> <output level file> [--stalker]";
+
+fn main()
+{
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() < 2
+    {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let with_stalker = args[2..].iter().any(|arg| arg == "--stalker");
+    let prefabs = prefabs::load_prefabs("entities.yaml");
+
+    let result = xsb::load_xsb_levels(&args[0], with_stalker, &prefabs)
+        .and_then(|levels|
+        {
+            println!("Converted {} levels", levels.len());
+            assets::save_levels(&levels, &args[1]).map_err(|e| e.to_string())
+        });
+
+    if let Err(error) = result
+    {
+        println!("{}", error);
+        exit(1);
+    }
+}
@@ -0,0 +1,173 @@
+//! Where the game looks. Levels that fit on screen sit still in the middle;
+//! bigger ones can scroll after the player, in whichever of the modes below
+//! `camera` in the level's meta names: fixed, follow, smooth or free.
+
+use assets::Level;
+use vectors::*;
+
+/// How far the player can get from the middle of the screen, in tiles,
+/// before a following camera moves.
+pub const DEADZONE: Vector2<f32> = Vector2 { components: [3.0, 1.5] };
+
+/// How quickly a smooth camera catches up. Higher is quicker.
+pub const SMOOTHING: f32 = 6.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CameraMode
+{
+    /// Stays on the middle of the level.
+    Fixed,
+    /// Jumps to keep the player in the deadzone, without showing past the
+    /// edges of the level.
+    Follow,
+    /// Like following, but eases after the player.
+    Smooth,
+    /// Stays on the player, even past the edges of the level.
+    Free
+}
+
+impl CameraMode
+{
+    pub fn from_name(name: &str) -> Option<Self>
+    {
+        match name
+        {
+            "fixed" => Some(CameraMode::Fixed),
+            "follow" => Some(CameraMode::Follow),
+            "smooth" => Some(CameraMode::Smooth),
+            "free" => Some(CameraMode::Free),
+            _ => None
+        }
+    }
+
+    /// The level's camera, or fixed if it doesn't say.
+    pub fn of_level(level: &Level) -> Self
+    {
+        level.meta.camera.as_ref().and_then(|name| CameraMode::from_name(name)).unwrap_or(CameraMode::Fixed)
+    }
+}
+
+pub struct Camera
+{
+    pub mode: CameraMode,
+    pub position: Vector2<f32>,
+    /// Where the camera's headed, which it's already at unless it's smooth.
+    target: Vector2<f32>,
+    /// The middle of the level, and how far from it the camera can go
+    /// before showing past the edges.
+    midpoint: Vector2<f32>,
+    reach: Vector2<f32>
+}
+
+impl Camera
+{
+    /// A camera for `level` on a screen `view` tiles across, looking at the
+    /// player where they start.
+    pub fn new(level: &Level, view: Vector2<f32>) -> Self
+    {
+        Camera::with_mode(level, view, CameraMode::of_level(level))
+    }
+
+    /// A camera that works some other way than the level says.
+    pub fn with_mode(level: &Level, view: Vector2<f32>, mode: CameraMode) -> Self
+    {
+        let size = vec2(level.width as f32, level.height as f32);
+        let reach = (size - view) * 0.5;
+        let mut camera = Camera
+        {
+            mode: mode,
+            position: level.midpoint,
+            target: level.midpoint,
+            midpoint: level.midpoint,
+            reach: vec2(reach.components[0].max(0.0), reach.components[1].max(0.0))
+        };
+        if camera.mode != CameraMode::Fixed
+        {
+            camera.position = camera.clamp(level.player_pos);
+            camera.target = camera.position;
+        }
+        camera
+    }
+
+    pub fn update(&mut self, player_pos: Vector2<f32>, dt: f64)
+    {
+        match self.mode
+        {
+            CameraMode::Fixed => (),
+            CameraMode::Free => self.position = player_pos,
+            CameraMode::Follow =>
+            {
+                self.target = self.clamp(self.deadzone_target(player_pos));
+                self.position = self.target;
+            },
+            CameraMode::Smooth =>
+            {
+                self.target = self.clamp(self.deadzone_target(player_pos));
+                let t = 1.0 - (-SMOOTHING * dt as f32).exp();
+                self.position = self.position + (self.target - self.position) * t;
+            }
+        }
+    }
+
+    /// The nearest place to the last target that has the player inside the
+    /// deadzone.
+    fn deadzone_target(&self, player_pos: Vector2<f32>) -> Vector2<f32>
+    {
+        let mut target = self.target;
+        for axis in 0..2
+        {
+            let offset = player_pos.components[axis] - target.components[axis];
+            let limit = DEADZONE.components[axis];
+            target.components[axis] += offset - offset.clamp(-limit, limit);
+        }
+        target
+    }
+
+    /// Keeps the screen inside the level, centring it on any side the level
+    /// is too small to fill.
+    fn clamp(&self, position: Vector2<f32>) -> Vector2<f32>
+    {
+        let mut clamped = position;
+        for axis in 0..2
+        {
+            let (middle, reach) = (self.midpoint.components[axis], self.reach.components[axis]);
+            clamped.components[axis] = position.components[axis].clamp(middle - reach, middle + reach);
+        }
+        clamped
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use serde_yaml;
+
+    use assets::parse_levels;
+    use prefabs::PrefabTable;
+
+    #[test]
+    pub fn follows_inside_the_level()
+    {
+        let prefabs: PrefabTable = serde_yaml::from_str(include_str!("../assets/entities.yaml")).unwrap();
+        let mut level = parse_levels(include_str!("../assets/packs/main/levels.yaml"), &prefabs).remove(0);
+        level.width = 40;
+        level.midpoint = vec2(19.5, 4.0);
+        level.meta.camera = Some("follow".to_string());
+
+        // The player starts at the left, so the camera starts at the edge.
+        let mut camera = Camera::new(&level, vec2(16.0, 9.0));
+        assert_eq!(camera.position, vec2(7.5, 4.0));
+
+        camera.update(vec2(12.0, 4.0), 0.1);
+        assert_eq!(camera.position, vec2(9.0, 4.0));
+        camera.update(vec2(8.0, 4.0), 0.1);
+        assert_eq!(camera.position, vec2(9.0, 4.0));
+        camera.update(vec2(100.0, 4.0), 0.1);
+        assert_eq!(camera.position, vec2(31.5, 4.0));
+
+        level.meta.camera = None;
+        assert_eq!(Camera::new(&level, vec2(16.0, 9.0)).position, level.midpoint);
+    }
+}
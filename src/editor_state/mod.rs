@@ -0,0 +1,280 @@
+use specs::World;
+
+use assets::{Level, Tile};
+use camera::{Camera, CameraMode};
+use game::Game;
+use game_state::{build_world, view_tiles, world_sprites};
+use input::Action;
+use prefabs::PrefabTable;
+use rendering::*;
+use state::*;
+use systems::visual::FG_LAYER;
+use vectors::*;
+
+const CURSOR_REGION: Vector2<u32> = Vector2 { components: [6, 7] };
+
+/// Edits the current level in place. With the default keys, arrows move the
+/// cursor, Z and X pick a tile from the palette, Space places it and Delete
+/// or Backspace erases the top tile under the cursor. C picks up the tile
+/// under the cursor. Page Up and Page Down change level, Tab play-tests, S
+/// saves every level in the pack back to its file and E goes back to the
+/// game.
+pub struct EditorState
+{
+    display: Display,
+    renderer: SpriteRenderer,
+    level: Level,
+    world: World,
+    palette: Vec<String>,
+    selected: usize,
+    cursor: Vector2<i32>,
+    /// Follows the cursor around levels too big for the screen.
+    camera: Camera,
+    status: String,
+    title: String
+}
+
+impl State for EditorState
+{
+    fn new(display: &Display, game: &mut Game) -> Self
+    {
+        let renderer = SpriteRenderer::new(display);
+
+        game.playtesting = false;
+        let level = game.levels[game.current_level].clone();
+        let world = build_world(&level, &game.prefabs);
+        let cursor = level.player_pos.round_i32();
+        let camera = Camera::with_mode(&level, view_tiles(game.resolution, game.tile_size), CameraMode::Follow);
+
+        EditorState
+        {
+            display: display.clone(),
+            renderer: renderer,
+            level: level,
+            world: world,
+            palette: game.prefabs.codes(),
+            selected: 0,
+            cursor: cursor,
+            camera: camera,
+            status: String::new(),
+            title: String::new()
+        }
+    }
+
+    fn update(&mut self, dt: f64, game: &mut Game) -> bool
+    {
+        let mut edited = false;
+        let mut exiting_state = false;
+
+        for action in game.input.actions()
+        {
+            match action
+            {
+                Action::MoveLeft => self.move_cursor(vec2(-1, 0)),
+                Action::MoveRight => self.move_cursor(vec2(1, 0)),
+                Action::MoveUp => self.move_cursor(vec2(0, 1)),
+                Action::MoveDown => self.move_cursor(vec2(0, -1)),
+                Action::PreviousTile => self.selected = (self.selected + self.palette.len() - 1) % self.palette.len(),
+                Action::NextTile => self.selected = (self.selected + 1) % self.palette.len(),
+                Action::PlaceTile =>
+                {
+                    place_tile(&mut self.level, &game.prefabs, &self.palette[self.selected], self.cursor.to_f32());
+                    edited = true;
+                },
+                Action::EraseTile =>
+                {
+                    edited |= erase_tile(&mut self.level, self.cursor.to_f32()).is_some();
+                },
+                Action::PickTile =>
+                {
+                    if let Some(tile) = top_tile(&self.level, self.cursor.to_f32())
+                    {
+                        self.selected = self.palette.iter().position(|code| *code == tile.code).unwrap_or(self.selected);
+                    }
+                },
+                Action::PreviousLevel | Action::NextLevel if self.commit(game) =>
+                {
+                    let count = game.levels.len();
+                    game.current_level = match action
+                    {
+                        Action::PreviousLevel => (game.current_level + count - 1) % count,
+                        _ => (game.current_level + 1) % count
+                    };
+                    self.level = game.levels[game.current_level].clone();
+                    self.cursor = self.level.player_pos.round_i32();
+                    self.camera = Camera::with_mode(&self.level, view_tiles(game.resolution, game.tile_size), CameraMode::Follow);
+                    edited = true;
+                },
+                Action::Save if self.commit(game) =>
+                {
+                    self.status = match game.save_levels()
+                    {
+                        Ok(()) => "saved".to_string(),
+                        Err(e) => format!("save failed: {}", e)
+                    };
+                },
+                Action::Playtest | Action::Edit if self.commit(game) =>
+                {
+                    game.playtesting = action == Action::Playtest;
+                    game.current_state = StateType::GameState;
+                    exiting_state = true;
+                    break;
+                },
+                _ => ()
+            }
+        }
+
+        if edited
+        {
+            self.world = build_world(&self.level, &game.prefabs);
+            self.status.clear();
+        }
+        self.camera.update(self.cursor.to_f32(), dt);
+
+        if exiting_state
+        {
+            self.set_title("Small World".to_string());
+        }
+        else
+        {
+            self.update_title(game);
+        }
+
+        !exiting_state
+    }
+
+    fn draw(&mut self, target: &mut Canvas, game: &mut Game)
+    {
+        let palette = game.settings.palette();
+        target.clear_color_srgb_and_depth(palette.background, 1.0);
+
+        let mut sprites = world_sprites(&self.world);
+        let cursor_pos = self.cursor.to_f32();
+        if let Some(prefab) = game.prefabs.get(&self.palette[self.selected])
+        {
+            for sprite in prefab.entities.iter().filter_map(|e| e.sprite.as_ref())
+            {
+                sprites.push(SpriteInstance { layer: FG_LAYER + 1, position: cursor_pos, region: vec2(sprite.region[0], sprite.region[1]) });
+            }
+        }
+        sprites.push(SpriteInstance { layer: FG_LAYER + 2, position: cursor_pos, region: CURSOR_REGION });
+
+        let projection = calculate_projection(game.resolution, game.tile_size);
+        self.renderer.draw(target, sprites, projection, self.camera.position, game.tile_size, palette.tint);
+    }
+
+    fn reload_graphics(&mut self, display: &Display, _game: &mut Game) -> Result<(), String>
+    {
+        self.renderer.reload(display)
+    }
+}
+
+impl EditorState
+{
+    fn move_cursor(&mut self, delta: Vector2<i32>)
+    {
+        let moved = self.cursor + delta;
+        let (x, y) = (moved.components[0], moved.components[1]);
+        if x >= 0 && y >= 0 && (x as usize) < self.level.width && (y as usize) < self.level.height
+        {
+            self.cursor = moved;
+        }
+    }
+
+    /// Hands the edited level back to the game, if it's playable.
+    fn commit(&mut self, game: &mut Game) -> bool
+    {
+        match self.level.rebuild(&game.prefabs)
+        {
+            Ok(level) =>
+            {
+                game.levels[game.current_level] = level.clone();
+                self.level = level;
+                true
+            },
+            Err(error) =>
+            {
+                self.status = error;
+                false
+            }
+        }
+    }
+
+    fn update_title(&mut self, game: &Game)
+    {
+        let code = &self.palette[self.selected];
+        let prefab_name = game.prefabs.get(code).map_or("?", |prefab| prefab.name.as_str());
+        let mut title = format!("Small World editor - {} - {} ({})", self.level.name, prefab_name, code);
+        if !self.status.is_empty()
+        {
+            title = format!("{} - {}", title, self.status);
+        }
+        self.set_title(title);
+    }
+
+    fn set_title(&mut self, title: String)
+    {
+        if title != self.title
+        {
+            set_window_title(&self.display, &title);
+            self.title = title;
+        }
+    }
+}
+
+/// Puts a tile on its prefab's layer, replacing whatever was on that layer
+/// there. There's only one player and one stalker, so placing either moves
+/// it.
+pub fn place_tile(level: &mut Level, prefabs: &PrefabTable, code: &str, pos: Vector2<f32>)
+{
+    let prefab = prefabs.get(code).expect(&format!("No prefab for tile code '{}'", code));
+    let layer = prefab.tile_layer();
+    let unique = prefabs.has_component(code, |e| e.player || e.tracker.is_some());
+
+    level.tiles.retain(|tile| !((tile.pos == pos && tile.layer == layer) || (unique && tile.code == code)));
+    level.tiles.push(Tile { code: code.to_string(), pos: pos, layer: layer });
+}
+
+/// Removes the topmost tile at a position, returning it.
+pub fn erase_tile(level: &mut Level, pos: Vector2<f32>) -> Option<Tile>
+{
+    let top = top_tile(level, pos).cloned();
+    if let Some(ref top) = top
+    {
+        level.tiles.retain(|tile| tile != top);
+    }
+    top
+}
+
+fn top_tile(level: &Level, pos: Vector2<f32>) -> Option<&Tile>
+{
+    level.tiles.iter().filter(|tile| tile.pos == pos).max_by_key(|tile| tile.layer as u32)
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use serde_yaml;
+
+    use assets::{parse_levels, TileLayer};
+
+    #[test]
+    pub fn edits_stay_playable()
+    {
+        let prefabs: PrefabTable = serde_yaml::from_str(include_str!("../../assets/entities.yaml")).unwrap();
+        let mut level = parse_levels(include_str!("../../assets/packs/main/levels.yaml"), &prefabs).remove(0);
+
+        place_tile(&mut level, &prefabs, "B", vec2(8.0, 4.0));
+        place_tile(&mut level, &prefabs, "P", vec2(9.0, 3.0));
+        assert_eq!(level.tiles.iter().filter(|t| t.code == "P").count(), 1);
+        assert_eq!(erase_tile(&mut level, vec2(8.0, 4.0)).map(|t| t.layer), Some(TileLayer::Objects));
+
+        let rebuilt = level.rebuild(&prefabs).unwrap();
+        assert_eq!(rebuilt.player_pos, vec2(9.0, 3.0));
+
+        place_tile(&mut level, &prefabs, "S", vec2(8.0, 4.0));
+        assert!(level.rebuild(&prefabs).is_err());
+    }
+}
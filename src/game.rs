@@ -1,4 +1,18 @@
-use assets::{self, Level};
+use std::cmp::min;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use assets::{self, get_asset_path, Level};
+use assets::packs::{self, Pack};
+use assets::watch::FileWatcher;
+use input::Input;
+use prefabs::{self, PrefabTable};
+use progress::Progress;
+use rendering::Transition;
+use save::{self, SaveData};
+use settings::{self, Settings};
 use state::{StateType};
 use vectors::*;
 
@@ -6,59 +20,265 @@ pub struct Game
 {
     pub resolution: (u32, u32),
     pub tile_size: u32,
-    pub input: GameInput,
+    pub input: Input,
     pub current_state: StateType,
+    /// The state that ran before this one, for going back to.
+    pub previous_state: StateType,
+    pub prefabs: PrefabTable,
+    pub packs: Vec<Pack>,
+    pub pack: Pack,
     pub levels: Vec<Level>,
+    /// The file each level was loaded from, for saving edits back.
+    pub level_files: Vec<PathBuf>,
     pub current_level: usize,
-    pub complete: bool
-}
-
-#[derive(Default)]
-pub struct GameInput
-{
-    pub left: bool,
-    pub right: bool,
-    pub up: bool,
-    pub down: bool,
-    pub any_key_pressed: bool
+    pub progress: Progress,
+    /// Where progress is saved, or None if it can't be (in which case it
+    /// only lasts until the game closes).
+    pub save_path: Option<PathBuf>,
+    /// The level each pack was left on, kept in the save.
+    pub last_levels: BTreeMap<String, usize>,
+    pub settings: Settings,
+    pub settings_path: Option<PathBuf>,
+    pub complete: bool,
+    pub playtesting: bool,
+    /// Moves to play back on the next level played, instead of the player's.
+    pub replay: Option<Vec<Vector2<f32>>>,
+    /// Where to record the moves made on each level finished.
+    pub record_path: Option<PathBuf>,
+    /// How the next state comes in, if not by fading.
+    pub transition: Option<Transition>,
+    pub watcher: FileWatcher,
+    /// Why the last attempt to reload levels or graphics failed, if it did.
+    pub level_error: Option<String>,
+    pub graphics_error: Option<String>
 }
 
 impl Game
 {
-    pub fn new(resolution: (u32, u32)) -> Self
+    /// Sets up the game with the saved progress and the pack last played, or
+    /// with `pack` (which skips straight to playing it). Fails if the prefabs,
+    /// the packs or the chosen pack's levels don't load.
+    pub fn new(resolution: (u32, u32), pack: Option<Pack>, settings: Settings, settings_path: Option<PathBuf>) -> Result<Self, String>
     {
-        Game
+        let prefabs = prefabs::try_load_prefabs("entities.yaml")?;
+        let packs = packs::find_packs()?;
+
+        let mut save_path = save::default_save_path();
+        let save_data = match save_path.as_ref().map(|path| save::load_save(path))
+        {
+            Some(Ok(data)) => data,
+            Some(Err(e)) =>
+            {
+                eprintln!("{} - progress won't be saved", e);
+                save_path = None;
+                SaveData::default()
+            },
+            None => SaveData::default()
+        };
+
+        let (pack, loaded, current_state) = match pack
+        {
+            Some(pack) =>
+            {
+                let loaded = pack.try_load_levels(&prefabs)?;
+                (pack, loaded, StateType::GameState)
+            },
+            None =>
+            {
+                let (pack, loaded) = first_working_pack(&packs, save_data.last_pack.as_deref(), &prefabs)?;
+                (pack, loaded, StateType::SplashScreen)
+            }
+        };
+        let (level_files, levels): (Vec<_>, Vec<_>) = loaded.into_iter().unzip();
+        let current_level = save_data.last_levels.get(&pack.id).map_or(0, |&level| min(level, levels.len() - 1));
+
+        let mut game = Game
         {
             resolution: resolution,
             tile_size: 16,
-            input: GameInput::default(),
-            current_state: StateType::SplashScreen,
-            levels: assets::load_levels("levels.yaml"),
-            current_level: 0,
-            complete: false
+            input: Input::default(),
+            current_state: current_state,
+            previous_state: current_state,
+            prefabs: prefabs,
+            packs: packs,
+            pack: pack,
+            levels: levels,
+            level_files: level_files,
+            current_level: current_level,
+            progress: save_data.progress,
+            save_path: save_path,
+            last_levels: save_data.last_levels,
+            settings: settings,
+            settings_path: settings_path,
+            complete: false,
+            playtesting: false,
+            replay: None,
+            transition: None,
+            record_path: None,
+            watcher: FileWatcher::new(Duration::from_millis(500)),
+            level_error: None,
+            graphics_error: None
+        };
+        game.watch_files();
+        Ok(game)
+    }
+
+    /// Switches to another pack, starting from its first level.
+    pub fn load_pack(&mut self, pack: Pack) -> Result<(), String>
+    {
+        let (level_files, levels) = pack.try_load_levels(&self.prefabs)?.into_iter().unzip();
+        self.pack = pack;
+        self.levels = levels;
+        self.level_files = level_files;
+        self.current_level = self.last_levels.get(&self.pack.id).map_or(0, |&level| min(level, self.levels.len() - 1));
+        self.watch_files();
+        self.save();
+        Ok(())
+    }
+
+    /// Writes progress to the save file, if there is one. Failing to save
+    /// isn't worth stopping the game over, so it's only reported.
+    pub fn save(&mut self)
+    {
+        self.unlock_packs();
+        self.last_levels.insert(self.pack.id.clone(), self.current_level);
+        if let Some(ref path) = self.save_path
+        {
+            let data = SaveData
+            {
+                version: save::SAVE_VERSION,
+                progress: self.progress.clone(),
+                last_pack: Some(self.pack.id.clone()),
+                last_levels: self.last_levels.clone()
+            };
+            if let Err(e) = save::write_save(&data, path)
+            {
+                eprintln!("Could not save progress to '{}': {}", path.display(), e);
+            }
         }
     }
-}
 
-impl GameInput
-{
-    pub fn dx(&self) -> f32
+    pub fn save_settings(&self)
+    {
+        if let Some(ref path) = self.settings_path
+        {
+            if let Err(e) = settings::write_settings(&self.settings, path)
+            {
+                eprintln!("Could not save settings to '{}': {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Remembers every pack that's been unlocked, so it stays that way.
+    fn unlock_packs(&mut self)
+    {
+        let unlocked: Vec<String> = self.packs.iter().filter(|pack| pack.is_unlocked(&self.progress)).map(|pack| pack.id.clone()).collect();
+        for id in unlocked
+        {
+            self.progress.unlock_pack(&id);
+        }
+    }
+
+    /// Loads the prefabs and the current pack again, keeping the current ones
+    /// if anything fails.
+    pub fn reload_levels(&mut self) -> Result<(), String>
+    {
+        let prefabs = prefabs::try_load_prefabs("entities.yaml")?;
+        let pack = packs::load_pack(&self.pack.dir)?;
+        let (level_files, levels): (Vec<_>, Vec<_>) = pack.try_load_levels(&prefabs)?.into_iter().unzip();
+        self.current_level = min(self.current_level, levels.len() - 1);
+        self.prefabs = prefabs;
+        self.pack = pack;
+        self.levels = levels;
+        self.level_files = level_files;
+        self.watch_files();
+        Ok(())
+    }
+
+    /// Writes every level back to the file it came from.
+    pub fn save_levels(&self) -> io::Result<()>
+    {
+        let mut files = self.level_files.clone();
+        files.dedup();
+        for file in files
+        {
+            let levels: Vec<Level> = self.levels.iter().zip(self.level_files.iter())
+                .filter(|&(_, level_file)| *level_file == file)
+                .map(|(level, _)| level.clone())
+                .collect();
+            assets::save_levels(&levels, get_asset_path(&file))?;
+        }
+        Ok(())
+    }
+
+    /// The key for the current level's results in the progress.
+    pub fn level_key(&self) -> String
+    {
+        self.level_key_at(self.current_level)
+    }
+
+    pub fn level_key_at(&self, index: usize) -> String
+    {
+        format!("{}/{}", self.pack.id, self.levels[index].name)
+    }
+
+    /// Levels open up one at a time, each once the one before it has been
+    /// finished.
+    pub fn level_unlocked(&self, index: usize) -> bool
+    {
+        let previous = index.checked_sub(1).map(|previous| self.level_key_at(previous));
+        self.progress.level_unlocked(previous.as_deref())
+    }
+
+    /// Whether a changed file holds levels or prefabs, rather than graphics.
+    pub fn is_level_file(&self, path: &Path) -> bool
     {
-        if self.left { -1.0 }
-        else if self.right { 1.0 }
-        else { 0.0 }
+        self.level_file_paths().iter().any(|file| *file == path)
     }
 
-    pub fn dy(&self) -> f32
+    fn level_file_paths(&self) -> Vec<PathBuf>
     {
-        if self.down { -1.0 }
-        else if self.up { 1.0 }
-        else { 0.0 }
+        let mut files = vec![get_asset_path("entities.yaml"), get_asset_path(prefabs::ANIMATIONS_PATH), get_asset_path(prefabs::PARTICLES_PATH), get_asset_path(self.pack.manifest_path())];
+        files.extend(self.pack.level_files().iter().map(get_asset_path));
+        files
     }
 
-    pub fn dir(&self) -> Vector2<f32>
+    fn watch_files(&mut self)
     {
-        if self.left || self.right { vec2(self.dx(), 0.0) }
-        else { vec2(0.0, self.dy()) }
+        self.watcher = FileWatcher::new(Duration::from_millis(500));
+        for path in self.level_file_paths()
+        {
+            self.watcher.watch(path);
+        }
+        for path in &GRAPHICS_FILES
+        {
+            self.watcher.watch(get_asset_path(path));
+        }
+    }
+
+    pub fn error_message(&self) -> Option<String>
+    {
+        let errors: Vec<&str> = self.level_error.iter().chain(self.graphics_error.iter()).map(|e| e.as_str()).collect();
+        if errors.is_empty() { None } else { Some(errors.join("\n")) }
+    }
+}
+
+pub const GRAPHICS_FILES: [&str; 11] = ["atlas.png", "font.png", "font.yaml", "splash_screen.png", "ending_screen.png",
+    "shaders/sprite.vs", "shaders/sprite.fs", "shaders/splash.vs", "shaders/splash.fs", "shaders/transition.vs", "shaders/transition.fs"];
+
+/// The pack last played and its levels, or the first pack whose levels load
+/// if that one's gone or broken, so that one bad pack can't stop the game
+/// starting. Broken packs are reported, and still listed to choose from.
+fn first_working_pack(packs: &[Pack], last_pack: Option<&str>, prefabs: &PrefabTable) -> Result<(Pack, Vec<(PathBuf, Level)>), String>
+{
+    let last = packs.iter().filter(|pack| Some(pack.id.as_str()) == last_pack);
+    for pack in last.chain(packs.iter().filter(|pack| Some(pack.id.as_str()) != last_pack))
+    {
+        match pack.try_load_levels(prefabs)
+        {
+            Ok(loaded) => return Ok((pack.clone(), loaded)),
+            Err(e) => eprintln!("Skipping pack '{}': {}", pack.id, e)
+        }
     }
+    Err("No level packs with levels that load".to_string())
 }
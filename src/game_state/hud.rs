@@ -0,0 +1,134 @@
+//! What's shown over a level while it's played: its name and number, the
+//! moves made against par, how many have been undone, how the objective is
+//! going and how long the stalker waits before following.
+
+use specs::{World, Join, Gate};
+
+use assets::{Level, Objective};
+use rendering::{Align, Text};
+use systems::*;
+use vectors::*;
+
+/// Pixels between the text and the edges of the screen.
+const MARGIN: i32 = 2;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HudInfo
+{
+    /// Counting from one.
+    pub level_number: usize,
+    pub level_name: String,
+    pub moves: u32,
+    pub par: Option<u32>,
+    pub undos: u32,
+    pub objective: Objective,
+    /// Buttons with blocks on them, out of how many there are.
+    pub buttons: (u32, u32),
+    /// Items collected, out of how many there are.
+    pub items: (u32, u32),
+    /// How long the slowest stalker waits before following, if there are
+    /// any.
+    pub stalker_delay: Option<f32>,
+    pub paused: bool
+}
+
+impl HudInfo
+{
+    pub fn gather(world: &World, level: &Level, index: usize, undos: u32, paused: bool) -> Self
+    {
+        let (player, collectible) = (world.read::<Player>().pass(), world.read::<Collectible>().pass());
+        let (tracker, motion) = (world.read::<PlayerTracker>().pass(), world.read::<Motion>().pass());
+
+        let (moves, collected) = (&player).join().next().map_or((0, 0), |player| (player.moves, player.collected));
+        let stalker_delay = (&tracker, &motion).join()
+            .map(|(_, motion)| motion.delay_remaining.max(0.0))
+            .reduce(f32::max);
+
+        HudInfo
+        {
+            level_number: index + 1,
+            level_name: level.name.clone(),
+            moves: moves,
+            par: level.meta.par,
+            undos: undos,
+            objective: level.meta.objective,
+            buttons: victory::buttons_held_by_blocks(world),
+            items: (collected, (&collectible).join().count() as u32),
+            stalker_delay: stalker_delay,
+            paused: paused
+        }
+    }
+}
+
+/// Lays the HUD out round the edges of a screen of `resolution` pixels,
+/// for a font `glyph_height` pixels tall.
+pub fn hud_texts(info: &HudInfo, resolution: (u32, u32), glyph_height: u32) -> Vec<Text>
+{
+    let (width, height) = (resolution.0 as i32, resolution.1 as i32);
+    let glyph_height = glyph_height as i32;
+    let (top, bottom) = (height - MARGIN, MARGIN + glyph_height);
+
+    let moves = match info.par
+    {
+        Some(par) => format!("Moves {} / par {}", info.moves, par),
+        None => format!("Moves {}", info.moves)
+    };
+    let mut texts = vec![
+        Text::new(format!("{}. {}", info.level_number, info.level_name), vec2(MARGIN, top), Align::Left),
+        Text::new(moves, vec2(width - MARGIN, top), Align::Right),
+        Text::new(format!("Undos {}", info.undos), vec2(MARGIN, bottom), Align::Left)
+    ];
+
+    let mut status = vec![match info.objective
+    {
+        Objective::Exit => "Reach the door".to_string(),
+        Objective::Buttons => format!("Buttons {}/{}", info.buttons.0, info.buttons.1)
+    }];
+    if info.items.1 > 0
+    {
+        status.push(format!("Items {}/{}", info.items.0, info.items.1));
+    }
+    if let Some(delay) = info.stalker_delay
+    {
+        status.push(format!("Stalker {:.1}s", delay));
+    }
+    texts.push(Text::new(status.join("  "), vec2(width - MARGIN, bottom), Align::Right));
+
+    if info.paused
+    {
+        texts.push(Text::new("Paused", vec2(width / 2, (height + glyph_height) / 2), Align::Centre));
+    }
+    texts
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use serde_yaml;
+
+    use assets::parse_levels;
+    use game_state::build_world;
+    use prefabs::PrefabTable;
+
+    #[test]
+    pub fn shows_the_level_and_its_objective()
+    {
+        let prefabs: PrefabTable = serde_yaml::from_str(include_str!("../../assets/entities.yaml")).unwrap();
+        let levels = parse_levels(include_str!("../../assets/packs/main/levels.yaml"), &prefabs);
+        let world = build_world(&levels[0], &prefabs);
+
+        let info = HudInfo::gather(&world, &levels[0], 0, 2, false);
+        assert_eq!(info.level_number, 1);
+        assert_eq!(info.moves, 0);
+        assert_eq!(info.undos, 2);
+
+        let texts = hud_texts(&info, (256, 144), 6);
+        assert_eq!(texts[0].string, format!("1. {}", levels[0].name));
+        assert!(texts[1].string.starts_with("Moves 0"));
+        assert_eq!(texts[2].string, "Undos 2");
+        assert_eq!(texts[1].position, vec2(254, 142));
+        assert_eq!(texts.len(), 4);
+    }
+}
@@ -1,226 +1,462 @@
-use glium::{DrawParameters, DepthTest, Depth, Blend};
-use glium::uniforms::{Sampler, MinifySamplerFilter, MagnifySamplerFilter, SamplerWrapFunction};
+use std::collections::VecDeque;
+use image::RgbaImage;
 use specs::{World, Planner, Join, Gate};
 
-use assets::{get_asset_string, get_asset_bytes};
+use assets::{Level, Objective};
+use camera::Camera;
 use game::Game;
+use input::{Action, MoveQueue};
+use prefabs::{PrefabTable, spawn_prefab};
+use progress::LevelResult;
 use rendering::*;
+use rendering::software::{self, SoftwareAtlas};
+use replay::{record_path, write_replay, Replay};
+use settings::Palette;
 use state::*;
 use systems::*;
 use vectors::*;
 
+pub mod hud;
+pub mod sequence;
+pub mod snapshot;
+
+use self::hud::{HudInfo, hud_texts};
+use self::sequence::Sequence;
+use self::snapshot::Snapshot;
+
 pub struct GameState
 {
-    shader: Shader,
-    mesh: Mesh,
-    atlas: TextureAtlas,
+    renderer: SpriteRenderer,
+    text: TextRenderer,
     planner: Planner<()>,
-    camera_pos: Vector2<f32>,
-    time: f64
+    camera: Camera,
+    time: f64,
+    /// The world as it was before each of the player's moves.
+    undo_stack: Vec<Snapshot>,
+    move_queue: MoveQueue,
+    /// Moves still to be played back, which take over from the player's.
+    replay: VecDeque<Vector2<f32>>,
+    /// The moves made so far, for recording.
+    moves: Vec<Vector2<f32>>,
+    /// How many moves have been taken back.
+    undos: u32,
+    /// What's playing out now the level's over, if it is.
+    sequence: Option<Sequence>,
+    paused: bool
 }
 
 impl State for GameState
 {
     fn new(display: &Display, game: &mut Game) -> Self
     {
-        let shader = load_shader(display, &get_asset_string("shaders/sprite.vs"), &get_asset_string("shaders/sprite.fs"));
-        let mesh = quad_mesh(display);
-        let atlas = load_texture_atlas(display, &get_asset_bytes("atlas.png"), 16);
-
-        let mut world = World::new();
-        world.register::<Position>();
-        world.register::<Sprite>();
-        world.register::<Motion>();
-        world.register::<Player>();
-        world.register::<Collision>();
-        world.register::<Hazard>();
-        world.register::<Goal>();
-        world.register::<PlayerTracker>();
-        world.register::<Button>();
-        world.register::<ButtonGate>();
-
-        let camera_pos: Vector2<f32>;
+        let renderer = SpriteRenderer::new(display);
+
+        let level = &game.levels[game.current_level];
+        let camera = Camera::new(level, view_tiles(game.resolution, game.tile_size));
+        let world = build_world(level, &game.prefabs);
+
+        let planner = Planner::new(world);
+
+        GameState
         {
-            let level = &game.levels[game.current_level];
-            camera_pos = level.midpoint;
-
-            world.create_now()
-                .with(Position(level.player_pos))
-                .with(Sprite { region: vec2(0, 0), layer: visual::ACTOR_LAYER })
-                .with(Motion::new(4.0))
-                .with(Player::default())
-                .build();
-
-            world.create_now()
-                .with(Position(level.stalker_pos))
-                .with(Sprite { region: vec2(0, 1), layer: visual::ACTOR_LAYER })
-                .with(Motion::new(4.0))
-                .with(Collision::BlocksPush)
-                .with(Hazard)
-                .with(PlayerTracker::new(0.05, level.initial_stalker_path.clone()))
-                .build();
-
-            for door in &level.doors
-            {
-                world.create_now()
-                    .with(Position(*door))
-                    .with(Sprite { region: vec2(1, 2), layer: visual::BG_LAYER })
-                    .build();
-
-                world.create_now()
-                    .with(Position(*door))
-                    .with(Sprite { region: vec2(0, 3), layer: visual::OBJECT_LAYER })
-                    .with(Collision::BlocksPush)
-                    .with(Goal)
-                    .build();
-            }
+            renderer: renderer,
+            text: TextRenderer::new(display),
+            planner: planner,
+            camera: camera,
+            time: 0.0,
+            undo_stack: Vec::new(),
+            move_queue: MoveQueue::new(game.settings.move_buffer),
+            replay: game.replay.take().unwrap_or_default().into(),
+            moves: Vec::new(),
+            undos: 0,
+            sequence: None,
+            paused: false
+        }
+    }
 
-            for &(style, pos) in &level.blocks
+    fn update(&mut self, dt: f64, game: &mut Game) -> bool
+    {
+        if let Some(mut sequence) = self.sequence
+        {
+            // The level stays still, but what's been thrown up settles.
+            self.planner.run_custom(move |arg| particles::update_particles(arg, dt));
+            self.planner.wait();
+            sequence.update(dt);
+            if game.input.pressed(Action::Confirm)
             {
-                world.create_now()
-                    .with(Position(pos))
-                    .with(Sprite { region: vec2(style, 2), layer: visual::BG_LAYER })
-                    .with(Collision::Obstacle)
-                    .build();
+                sequence.skip();
             }
-
-            for push_block in &level.push_blocks
+            self.sequence = Some(sequence);
+            if sequence.finished()
             {
-                world.create_now()
-                    .with(Position(*push_block))
-                    .with(Motion::new(4.0))
-                    .with(Sprite { region: vec2(1, 3), layer: visual::OBJECT_LAYER })
-                    .with(Collision::Pushable)
-                    .build();
+                self.finish_sequence(sequence, game);
+                return false;
             }
+            return true;
+        }
 
-            for button in &level.buttons
+        if game.input.pressed(Action::Edit) && (game.playtesting || cfg!(debug_assertions))
+        {
+            game.current_state = StateType::Editor;
+            return false;
+        }
+        if game.input.pressed(Action::Back) && !game.playtesting
+        {
+            game.current_state = StateType::LevelSelect;
+            return false;
+        }
+        if game.input.pressed(Action::Pause)
+        {
+            self.paused = !self.paused;
+        }
+        if game.input.pressed(Action::Hud)
+        {
+            game.settings.show_hud = !game.settings.show_hud;
+            game.save_settings();
+        }
+        if self.paused
+        {
+            return true;
+        }
+
+        self.time += dt;
+        if game.input.pressed_or_repeated(Action::Undo)
+        {
+            if let Some(snapshot) = self.undo_stack.pop()
             {
-                world.create_now()
-                    .with(Position(*button))
-                    .with(Sprite { region: vec2(2, 3), layer: visual::BG_LAYER })
-                    .with(Button(false))
-                    .build();
+                snapshot.restore(self.planner.mut_world());
+                self.moves.pop();
+                self.undos += 1;
             }
+            self.move_queue.clear();
+        }
+
+        for dir in game.input.presses().iter().filter_map(|action| action.direction())
+        {
+            self.move_queue.push(dir);
+        }
 
-            for gate in &level.gates
+        // Moves are only made once everything's finished the last one, the
+        // same for live play, replays and the replay checker, so recorded
+        // moves play back just as they were made.
+        let moves_before = player_moves(self.planner.mut_world());
+        let player_control_direction = if !world_settled(self.planner.mut_world())
+        {
+            vec2(0.0, 0.0)
+        }
+        else if let Some(dir) = self.replay.pop_front()
+        {
+            dir
+        }
+        else
+        {
+            self.move_queue.next(game.input.dir())
+        };
+        let turn_start = if player_control_direction != vec2(0.0, 0.0)
+        {
+            Some(Snapshot::take(self.planner.mut_world()))
+        }
+        else
+        {
+            None
+        };
+
+        step_world(&mut self.planner, player_control_direction, dt * game.settings.animation_speed as f64);
+
+        if let Some(snapshot) = turn_start
+        {
+            if player_moves(self.planner.mut_world()) > moves_before
             {
-                world.create_now()
-                    .with(Position(*gate))
-                    .with(Sprite { region: vec2(0, 4), layer: visual::BG_LAYER })
-                    .with(Collision::Obstacle)
-                    .with(ButtonGate(false))
-                    .build();
+                self.undo_stack.push(snapshot);
+                self.moves.push(player_control_direction);
             }
         }
 
-        let planner = Planner::new(world);
+        let player_pos = player_position(self.planner.mut_world());
+        self.camera.update(player_pos, dt);
+
+        let (exiting_state, finished): (bool, bool);
 
-        GameState
         {
-            shader: shader,
-            mesh: mesh,
-            atlas: atlas,
-            planner: planner,
-            camera_pos: camera_pos,
-            time: 0.0
+            let world = self.planner.mut_world();
+            let level = &game.levels[game.current_level];
+            let victory = match level.meta.objective
+            {
+                Objective::Exit => victory::determine_victory_from_goal(world),
+                Objective::Buttons => victory::determine_victory_from_buttons(world)
+            };
+            let catcher = victory::hazard_touching_player(world);
+            if victory && game.playtesting
+            {
+                game.current_state = StateType::Editor;
+            }
+            else if victory
+            {
+                let mut result = level_result(world, level.meta.par);
+                result.time_ms = Some((self.time * 1000.0) as u32);
+                self.sequence = Some(Sequence::complete(result, level.meta.par));
+                let key = game.level_key();
+                game.progress.record(&key, result);
+            }
+            else if let Some(catcher) = catcher
+            {
+                particles::burst_caught(world);
+                self.sequence = Some(Sequence::caught(catcher));
+            }
+            exiting_state = victory && game.playtesting;
+            finished = victory && !game.playtesting;
+        }
+        if finished
+        {
+            self.record_moves(game);
         }
-    }
 
-    fn update(&mut self, dt: f64, game: &mut Game) -> bool
-    {
-        self.time += dt;
-        let player_control_direction = game.input.dir();
+        self.planner.wait();
 
-        self.planner.run_custom(|arg| buttons::check_button_presses(arg));
-        self.planner.run_custom(|arg| buttons::open_and_close_gates(arg));
+        !exiting_state
+    }
 
-        self.planner.run_custom(|arg| motion::track_player(arg));
-        self.planner.run_custom(move |arg| motion::player_controls(arg, player_control_direction));
-        self.planner.run_custom(|arg| motion::push_stuff(arg));
-        self.planner.run_custom(move |arg| motion::move_towards_destinations(arg, dt));
+    fn draw(&mut self, target: &mut Canvas, game: &mut Game)
+    {
+        let palette = game.settings.palette();
+        let projection = calculate_projection(game.resolution, game.tile_size);
+        let mut sprites = world_sprites(self.planner.mut_world());
+        let dimmed = self.paused || self.sequence.is_some_and(|sequence| sequence.dims());
+        let mut tint = if dimmed
+        {
+            [palette.tint[0] * 0.5, palette.tint[1] * 0.5, palette.tint[2] * 0.5, palette.tint[3]]
+        }
+        else
+        {
+            palette.tint
+        };
+        let mut background = palette.background;
+        let mut camera_pos = self.camera.position;
+        let mut texts = Vec::new();
+        if let Some(sequence) = self.sequence
+        {
+            // Flashing lightens everything towards white.
+            let flash = sequence.flash();
+            let lighten = |channel: f32| channel + (1.0 - channel) * flash;
+            background = (lighten(background.0), lighten(background.1), lighten(background.2), background.3);
+            for channel in tint.iter_mut().take(3)
+            {
+                *channel *= 1.0 + 3.0 * flash;
+            }
+            camera_pos = camera_pos + sequence.shake();
+            sprites.extend(sequence.sprites());
+            texts.extend(sequence.texts(game.resolution, self.text.metrics.line_height));
+        }
+        target.clear_color_srgb_and_depth(background, 1.0);
+        self.renderer.draw(target, sprites, projection, camera_pos, game.tile_size, tint);
+        let particles = world_particles(self.planner.mut_world());
+        self.renderer.draw_particles(target, &particles, projection, camera_pos, visual::FG_LAYER, tint);
 
-        self.planner.run_custom(|arg| buttons::update_gate_sprites(arg));
+        if game.settings.show_hud
+        {
+            let info = HudInfo::gather(self.planner.mut_world(), &game.levels[game.current_level], game.current_level, self.undos, self.paused);
+            texts.extend(hud_texts(&info, game.resolution, self.text.metrics.cell[1]));
+        }
+        self.text.draw(target, &texts, game.resolution, visual::UI_LAYER, palette.text);
+    }
 
-        let exiting_state: bool;
+    fn reload_graphics(&mut self, display: &Display, _game: &mut Game) -> Result<(), String>
+    {
+        self.renderer.reload(display)?;
+        self.text.reload(display)
+    }
+}
 
+impl GameState
+{
+    /// Moves on once a sequence has played out: past a finished level, or
+    /// back to the start of one the player was caught in.
+    fn finish_sequence(&mut self, sequence: Sequence, game: &mut Game)
+    {
+        match sequence
         {
-            let world = self.planner.mut_world();
-            let victory = victory::determine_victory_from_goal(world);
-            let gameover = victory::determine_gameover_from_hazard(world);
-            if victory
+            Sequence::Complete { .. } =>
             {
+                let player_pos = player_position(self.planner.mut_world());
+                let offset = (player_pos - self.camera.position) * game.tile_size as f32;
+                let focus = [0.5 + offset.components[0] / game.resolution.0 as f32, 0.5 + offset.components[1] / game.resolution.1 as f32];
+                game.transition = Some(Transition::iris(focus));
+
                 game.current_level += 1;
                 if game.current_level >= game.levels.len()
                 {
                     game.complete = true;
+                    game.progress.complete_pack(&game.pack.id);
                     game.current_level = 0;
                     game.current_state = StateType::EndingState;
                 }
-            }
-            exiting_state = victory | gameover;
+                game.save();
+            },
+            Sequence::Caught { .. } => game.transition = Some(Transition::new(TransitionKind::Dissolve))
         }
+    }
 
-        self.planner.wait();
-
-        !exiting_state
+    /// Writes the moves that finished the level to the record file, if
+    /// there is one.
+    fn record_moves(&self, game: &Game)
+    {
+        if let Some(ref path) = game.record_path
+        {
+            let replay = Replay::new(&game.pack.id, &game.levels[game.current_level].name, &self.moves);
+            if let Err(e) = write_replay(&replay, &record_path(path, &game.pack.id, game.current_level + 1))
+            {
+                eprintln!("{}", e);
+            }
+        }
     }
+}
 
-    fn draw(&mut self, target: &mut Frame, game: &mut Game)
+pub fn build_world(level: &Level, prefabs: &PrefabTable) -> World
+{
+    let mut world = World::new();
+    world.register::<Position>();
+    world.register::<Sprite>();
+    world.register::<Motion>();
+    world.register::<Player>();
+    world.register::<Collision>();
+    world.register::<Hazard>();
+    world.register::<Goal>();
+    world.register::<PlayerTracker>();
+    world.register::<Button>();
+    world.register::<ButtonGate>();
+    world.register::<Collectible>();
+    world.register::<Animation>();
+    world.register::<Emitter>();
+
+    for tile in &level.tiles
     {
-        target.clear_color_srgb_and_depth((0.75, 0.75, 0.75, 1.0), 1.0);
+        let prefab = prefabs.get(&tile.code).expect(&format!("No prefab for tile code '{}'", tile.code));
+        spawn_prefab(&mut world, level, &tile.code, prefab, tile.pos);
+    }
 
-        let colormap = Sampler::new(&self.atlas.texture)
-            .minify_filter(MinifySamplerFilter::Nearest)
-            .magnify_filter(MagnifySamplerFilter::Nearest)
-            .wrap_function(SamplerWrapFunction::Clamp);
+    world
+}
 
-        let projection = calculate_projection(game.resolution, game.tile_size);
+/// Runs one frame's worth of game logic, with the player trying to move in
+/// `dir`.
+pub fn step_world(planner: &mut Planner<()>, dir: Vector2<f32>, dt: f64)
+{
+    planner.run_custom(buttons::check_button_presses);
+    planner.run_custom(buttons::open_and_close_gates);
+    planner.run_custom(collectibles::collect_items);
+
+    planner.run_custom(motion::track_player);
+    planner.run_custom(move |arg| motion::player_controls(arg, dir));
+    planner.run_custom(motion::push_stuff);
+    planner.run_custom(move |arg| motion::move_towards_destinations(arg, dt));
+
+    planner.run_custom(buttons::update_gate_sprites);
+    planner.run_custom(collectibles::update_collectible_sprites);
+    planner.run_custom(animation::choose_clips);
+    planner.run_custom(move |arg| animation::animate_sprites(arg, dt));
+    planner.run_custom(particles::trigger_emitters);
+    planner.run_custom(move |arg| particles::update_particles(arg, dt));
+}
 
-        {
-            let world = self.planner.mut_world();
-            let (position, sprite) = (world.read::<Position>().pass(), world.read::<Sprite>().pass());
+pub fn world_particles(world: &World) -> Vec<ParticleSprite>
+{
+    (&world.read::<Emitter>().pass()).join().flat_map(|emitter| emitter.sprites()).collect()
+}
 
-            let mut render_buffer = Vec::new();
+pub fn world_sprites(world: &World) -> Vec<SpriteInstance>
+{
+    let (position, sprite) = (world.read::<Position>().pass(), world.read::<Sprite>().pass());
+    (&position, &sprite).join()
+        .map(|(position, sprite)| SpriteInstance { layer: sprite.layer, position: position.0, region: sprite.region })
+        .collect()
+}
 
-            for (position, sprite) in (&position, &sprite).join()
-            {
-                let (uv_offset, uv_scale) = self.atlas.get_uv_offset_scale(sprite.region.components[0], sprite.region.components[1]);
-                let pixel_position = (position.0 * game.tile_size as f32).round_i32();
-                let rounded_position = vec2(pixel_position.components[0] as f32, pixel_position.components[1] as f32) * (1.0 / game.tile_size as f32);
+/// Draws a level as it starts, centred the way the game shows it, without
+/// a GPU.
+pub fn render_level(level: &Level, prefabs: &PrefabTable, atlas: &SoftwareAtlas, resolution: (u32, u32), tile_size: u32, palette: &Palette) -> RgbaImage
+{
+    let world = build_world(level, prefabs);
+    let camera = Camera::new(level, view_tiles(resolution, tile_size));
+    software::render_sprites(atlas, world_sprites(&world), resolution, camera.position, tile_size, palette.background, palette.tint)
+}
 
-                render_buffer.push((sprite.layer, rounded_position.components, uv_offset, uv_scale));
-            }
+/// Whether everything has stopped moving.
+pub fn world_settled(world: &World) -> bool
+{
+    !(&world.read::<Motion>().pass()).join().any(|motion| motion.destination.is_some())
+}
+
+/// How many tiles fit on the screen each way.
+pub fn view_tiles(resolution: (u32, u32), tile_size: u32) -> Vector2<f32>
+{
+    vec2(resolution.0 as f32, resolution.1 as f32) * (1.0 / tile_size as f32)
+}
+
+fn player_position(world: &World) -> Vector2<f32>
+{
+    let (position, player) = (world.read::<Position>().pass(), world.read::<Player>().pass());
+    let (position, _) = (&position, &player).join().next().expect("No player found");
+    position.0
+}
 
-            render_buffer.sort_by_key(|k| k.0);
+/// How many moves the player has made.
+pub fn player_moves(world: &World) -> u32
+{
+    (&world.read::<Player>().pass()).join().next().expect("No player found").moves
+}
+
+fn level_result(world: &World, par: Option<u32>) -> LevelResult
+{
+    let (player, collectible) = (world.read::<Player>().pass(), world.read::<Collectible>().pass());
+    let player = (&player).join().next().expect("No player found");
+    let total_collectibles = (&collectible).join().count() as u32;
+    LevelResult::new(player.moves, player.collected, total_collectibles, par)
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+    use image;
+    use serde_yaml;
+
+    use assets::parse_levels;
+    use settings::PALETTES;
+
+    /// Set to write the images the goldens are compared against, after
+    /// changing how levels look on purpose.
+    const BLESS_VAR: &str = "SMALLWORLD_BLESS";
+
+    #[test]
+    pub fn levels_look_like_the_goldens()
+    {
+        let prefabs: PrefabTable = serde_yaml::from_str(include_str!("../../assets/entities.yaml")).unwrap();
+        let levels = parse_levels(include_str!("../../assets/packs/main/levels.yaml"), &prefabs);
+        let atlas = SoftwareAtlas::from_png(include_bytes!("../../assets/atlas.png")).unwrap();
+        let golden_folder = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+
+        let mut mismatched = Vec::new();
+        for (index, level) in levels.iter().enumerate()
+        {
+            let frame = render_level(level, &prefabs, &atlas, (256, 144), 16, &PALETTES[0]);
+            let golden_path = golden_folder.join(format!("main-{}.png", index + 1));
+            if env::var_os(BLESS_VAR).is_some()
+            {
+                software::write_png(&frame, &golden_path).unwrap();
+                continue;
+            }
 
-            for (_layer, position, uv_offset, uv_scale) in render_buffer
+            let golden = image::open(&golden_path).unwrap_or_else(|e| panic!("Could not open '{}': {}", golden_path.display(), e)).to_rgba();
+            if golden.dimensions() != frame.dimensions() || golden.into_raw() != frame.clone().into_raw()
             {
-                target.draw(
-                    &self.mesh.0,
-                    &self.mesh.1,
-                    &self.shader,
-                    &uniform!
-                    {
-                        projection: projection,
-                        camera_pos: self.camera_pos.components,
-                        colormap: colormap,
-                        position: position,
-                        uv_offset: uv_offset,
-                        uv_scale: uv_scale
-                    },
-                    &DrawParameters
-                    {
-                        depth: Depth
-                        {
-                            test: DepthTest::IfLess,
-                            write: false,
-                            .. Default::default()
-                        },
-                        blend: Blend::alpha_blending(),
-                        .. Default::default()
-                    }).unwrap();
+                let actual_path = env::temp_dir().join(format!("smallworld-main-{}.png", index + 1));
+                software::write_png(&frame, &actual_path).unwrap();
+                mismatched.push(format!("{} (got {})", golden_path.display(), actual_path.display()));
             }
         }
+        assert!(mismatched.is_empty(), "Levels don't match their goldens: {}", mismatched.join(", "));
     }
 }
@@ -0,0 +1,163 @@
+//! Short scenes played over a level when it ends, before the game moves on:
+//! being caught freezes the level, flashes, shakes and marks what did it,
+//! and finishing sums up the moves. Neither takes any input, except that
+//! the summary can be skipped.
+
+use progress::{LevelResult, MAX_STARS};
+use rendering::{Align, SpriteInstance, Text};
+use systems::visual::FG_LAYER;
+use vectors::*;
+
+pub const CAUGHT_TIME: f32 = 1.2;
+pub const COMPLETE_TIME: f32 = 2.0;
+/// How long the screen flashes for at the start of being caught.
+const FLASH_TIME: f32 = 0.15;
+/// How far the screen shakes, in tiles, at its worst.
+const SHAKE: f32 = 0.2;
+/// How many times a second the mark on the catcher blinks.
+const BLINK_RATE: f32 = 8.0;
+const MARK_REGION: Vector2<u32> = Vector2 { components: [6, 7] };
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Sequence
+{
+    /// The player's been caught by whatever's at `catcher`.
+    Caught { catcher: Vector2<f32>, time: f32 },
+    /// The level's been finished with `result`, against `par`.
+    Complete { result: LevelResult, par: Option<u32>, time: f32 }
+}
+
+impl Sequence
+{
+    pub fn caught(catcher: Vector2<f32>) -> Self
+    {
+        Sequence::Caught { catcher: catcher, time: 0.0 }
+    }
+
+    pub fn complete(result: LevelResult, par: Option<u32>) -> Self
+    {
+        Sequence::Complete { result: result, par: par, time: 0.0 }
+    }
+
+    pub fn update(&mut self, dt: f64)
+    {
+        match *self
+        {
+            Sequence::Caught { ref mut time, .. } | Sequence::Complete { ref mut time, .. } => *time += dt as f32
+        }
+    }
+
+    /// Jumps to the end, for summaries the player doesn't want to wait for.
+    pub fn skip(&mut self)
+    {
+        if let Sequence::Complete { ref mut time, .. } = *self
+        {
+            *time = COMPLETE_TIME;
+        }
+    }
+
+    pub fn finished(&self) -> bool
+    {
+        match *self
+        {
+            Sequence::Caught { time, .. } => time >= CAUGHT_TIME,
+            Sequence::Complete { time, .. } => time >= COMPLETE_TIME
+        }
+    }
+
+    /// How far to move the camera, in tiles, dying down over the sequence.
+    pub fn shake(&self) -> Vector2<f32>
+    {
+        match *self
+        {
+            Sequence::Caught { time, .. } =>
+            {
+                let strength = SHAKE * (1.0 - time / CAUGHT_TIME).max(0.0);
+                vec2((time * 61.0).sin(), (time * 47.0).cos()) * strength
+            },
+            Sequence::Complete { .. } => vec2(0.0, 0.0)
+        }
+    }
+
+    /// How white the screen is, from 0 to 1.
+    pub fn flash(&self) -> f32
+    {
+        match *self
+        {
+            Sequence::Caught { time, .. } => (1.0 - time / FLASH_TIME).max(0.0),
+            Sequence::Complete { .. } => 0.0
+        }
+    }
+
+    /// Whether the level should be dimmed behind the sequence's text.
+    pub fn dims(&self) -> bool
+    {
+        matches!(*self, Sequence::Complete { .. })
+    }
+
+    /// A blinking mark over whatever caught the player.
+    pub fn sprites(&self) -> Vec<SpriteInstance>
+    {
+        match *self
+        {
+            Sequence::Caught { catcher, time } if ((time * BLINK_RATE) as u32).is_multiple_of(2) =>
+                vec![SpriteInstance { layer: FG_LAYER, position: catcher, region: MARK_REGION }],
+            _ => Vec::new()
+        }
+    }
+
+    /// The summary of a finished level, in the middle of a screen of
+    /// `resolution` pixels.
+    pub fn texts(&self, resolution: (u32, u32), line_height: u32) -> Vec<Text>
+    {
+        let (result, par) = match *self
+        {
+            Sequence::Complete { result, par, .. } => (result, par),
+            Sequence::Caught { .. } => return Vec::new()
+        };
+
+        let mut lines = vec!["Level complete!".to_string()];
+        lines.push(match par
+        {
+            Some(par) => format!("{} moves, par {}", result.moves, par),
+            None => format!("{} moves", result.moves)
+        });
+        if result.total_collectibles > 0
+        {
+            lines.push(format!("{} of {} items", result.collected, result.total_collectibles));
+        }
+        lines.push(format!("{} of {} stars", result.stars, MAX_STARS));
+
+        let (width, height) = (resolution.0 as i32, resolution.1 as i32);
+        let top = (height + (lines.len() as u32 * line_height) as i32) / 2;
+        vec![Text::new(lines.join("\n"), vec2(width / 2, top), Align::Centre)]
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    pub fn caught_shakes_and_settles()
+    {
+        let mut sequence = Sequence::caught(vec2(3.0, 2.0));
+        assert_eq!(sequence.flash(), 1.0);
+        assert_eq!(sequence.sprites()[0].position, vec2(3.0, 2.0));
+
+        sequence.update(0.6);
+        assert_eq!(sequence.flash(), 0.0);
+        assert!(!sequence.finished());
+
+        sequence.update(0.6);
+        assert!(sequence.finished());
+        assert_eq!(sequence.shake(), vec2(0.0, 0.0));
+
+        let mut complete = Sequence::complete(LevelResult::new(12, 0, 0, Some(14)), Some(14));
+        assert_eq!(complete.texts((256, 144), 7)[0].string, "Level complete!\n12 moves, par 14\n3 of 3 stars");
+        complete.skip();
+        assert!(complete.finished());
+    }
+}
@@ -0,0 +1,108 @@
+use specs::{Component, Entity, Gate, Join, World};
+
+use systems::*;
+
+/// Everything about a world that can change during play, for putting it
+/// back the way it was.
+#[derive(Clone)]
+pub struct Snapshot
+{
+    positions: Vec<(Entity, Position)>,
+    motions: Vec<(Entity, Motion)>,
+    players: Vec<(Entity, Player)>,
+    trackers: Vec<(Entity, PlayerTracker)>,
+    collisions: Vec<(Entity, Collision)>,
+    buttons: Vec<(Entity, Button)>,
+    gates: Vec<(Entity, ButtonGate)>,
+    collectibles: Vec<(Entity, Collectible)>,
+    sprites: Vec<(Entity, Sprite)>
+}
+
+impl Snapshot
+{
+    pub fn take(world: &World) -> Self
+    {
+        Snapshot
+        {
+            positions: components(world),
+            motions: components(world),
+            players: components(world),
+            trackers: components(world),
+            collisions: components(world),
+            buttons: components(world),
+            gates: components(world),
+            collectibles: components(world),
+            sprites: components(world)
+        }
+    }
+
+    pub fn restore(self, world: &World)
+    {
+        restore_components(world, self.positions);
+        restore_components(world, self.motions);
+        restore_components(world, self.players);
+        restore_components(world, self.trackers);
+        restore_components(world, self.collisions);
+        restore_components(world, self.buttons);
+        restore_components(world, self.gates);
+        restore_components(world, self.collectibles);
+        restore_components(world, self.sprites);
+    }
+}
+
+fn components<T>(world: &World) -> Vec<(Entity, T)>
+    where T: Component + Clone
+{
+    let (entities, storage) = (world.entities(), world.read::<T>().pass());
+    (&entities, &storage).join().map(|(entity, component)| (entity, component.clone())).collect()
+}
+
+fn restore_components<T>(world: &World, components: Vec<(Entity, T)>)
+    where T: Component
+{
+    let mut storage = world.write::<T>().pass();
+    for (entity, component) in components
+    {
+        storage.insert(entity, component);
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use serde_yaml;
+    use specs::Planner;
+
+    use assets::parse_levels;
+    use game_state::{build_world, step_world};
+    use prefabs::PrefabTable;
+    use vectors::*;
+
+    fn player_pos(world: &World) -> Vector2<f32>
+    {
+        let (position, player) = (world.read::<Position>().pass(), world.read::<Player>().pass());
+        (&position, &player).join().next().unwrap().0 .0
+    }
+
+    #[test]
+    pub fn restores_the_world()
+    {
+        let prefabs: PrefabTable = serde_yaml::from_str(include_str!("../../assets/entities.yaml")).unwrap();
+        let level = parse_levels(include_str!("../../assets/packs/main/levels.yaml"), &prefabs).remove(0);
+        let mut planner = Planner::new(build_world(&level, &prefabs));
+
+        let snapshot = Snapshot::take(planner.mut_world());
+        for _ in 0..20
+        {
+            step_world(&mut planner, vec2(1.0, 0.0), 0.05);
+        }
+        assert!(player_pos(planner.mut_world()) != level.player_pos);
+
+        snapshot.restore(planner.mut_world());
+        assert_eq!(player_pos(planner.mut_world()), level.player_pos);
+        let player = planner.mut_world().read::<Player>().pass();
+        assert_eq!((&player).join().next().unwrap().moves, 0);
+    }
+}
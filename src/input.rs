@@ -0,0 +1,397 @@
+//! Turns key presses into the actions the game cares about, through a
+//! binding table that can give each action any number of keys. States ask
+//! about actions rather than keys, so rebinding reaches all of them.
+
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use glium::glutin::{ElementState, VirtualKeyCode};
+
+use vectors::*;
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Action
+{
+    #[serde(rename = "move_left")]
+    MoveLeft,
+    #[serde(rename = "move_right")]
+    MoveRight,
+    #[serde(rename = "move_up")]
+    MoveUp,
+    #[serde(rename = "move_down")]
+    MoveDown,
+    #[serde(rename = "undo")]
+    Undo,
+    #[serde(rename = "restart")]
+    Restart,
+    /// Skips to the next level, in debug builds.
+    #[serde(rename = "skip")]
+    Skip,
+    #[serde(rename = "pause")]
+    Pause,
+    /// Shows or hides the level's HUD.
+    #[serde(rename = "hud")]
+    Hud,
+    #[serde(rename = "confirm")]
+    Confirm,
+    #[serde(rename = "back")]
+    Back,
+    #[serde(rename = "quit")]
+    Quit,
+    #[serde(rename = "options")]
+    Options,
+    /// Opens the editor from the game, or leaves it.
+    #[serde(rename = "edit")]
+    Edit,
+    #[serde(rename = "playtest")]
+    Playtest,
+    #[serde(rename = "previous_tile")]
+    PreviousTile,
+    #[serde(rename = "next_tile")]
+    NextTile,
+    #[serde(rename = "place_tile")]
+    PlaceTile,
+    #[serde(rename = "erase_tile")]
+    EraseTile,
+    #[serde(rename = "pick_tile")]
+    PickTile,
+    #[serde(rename = "previous_level")]
+    PreviousLevel,
+    #[serde(rename = "next_level")]
+    NextLevel,
+    #[serde(rename = "save")]
+    Save
+}
+
+pub const ACTIONS: [Action; 23] = [Action::MoveLeft, Action::MoveRight, Action::MoveUp, Action::MoveDown,
+    Action::Undo, Action::Restart, Action::Skip, Action::Pause, Action::Hud, Action::Confirm, Action::Back, Action::Quit,
+    Action::Options, Action::Edit, Action::Playtest, Action::PreviousTile, Action::NextTile, Action::PlaceTile,
+    Action::EraseTile, Action::PickTile, Action::PreviousLevel, Action::NextLevel, Action::Save];
+
+impl Action
+{
+    /// Which way a move action goes.
+    pub fn direction(&self) -> Option<Vector2<f32>>
+    {
+        match *self
+        {
+            Action::MoveLeft => Some(vec2(-1.0, 0.0)),
+            Action::MoveRight => Some(vec2(1.0, 0.0)),
+            Action::MoveUp => Some(vec2(0.0, 1.0)),
+            Action::MoveDown => Some(vec2(0.0, -1.0)),
+            _ => None
+        }
+    }
+
+    /// The action's name in the settings file.
+    pub fn name(&self) -> &'static str
+    {
+        match *self
+        {
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::Undo => "undo",
+            Action::Restart => "restart",
+            Action::Skip => "skip",
+            Action::Pause => "pause",
+            Action::Hud => "hud",
+            Action::Confirm => "confirm",
+            Action::Back => "back",
+            Action::Quit => "quit",
+            Action::Options => "options",
+            Action::Edit => "edit",
+            Action::Playtest => "playtest",
+            Action::PreviousTile => "previous_tile",
+            Action::NextTile => "next_tile",
+            Action::PlaceTile => "place_tile",
+            Action::EraseTile => "erase_tile",
+            Action::PickTile => "pick_tile",
+            Action::PreviousLevel => "previous_level",
+            Action::NextLevel => "next_level",
+            Action::Save => "save"
+        }
+    }
+}
+
+/// The keys for each action, by name, as in "Left" or "Space".
+pub type Bindings = BTreeMap<Action, Vec<String>>;
+
+pub fn default_bindings() -> Bindings
+{
+    let bindings: [(Action, &[&str]); 23] =
+    [
+        (Action::MoveLeft, &["Left"]),
+        (Action::MoveRight, &["Right"]),
+        (Action::MoveUp, &["Up"]),
+        (Action::MoveDown, &["Down"]),
+        (Action::Undo, &["Z", "U"]),
+        (Action::Restart, &["R"]),
+        (Action::Skip, &["N"]),
+        (Action::Pause, &["P"]),
+        (Action::Hud, &["H"]),
+        (Action::Confirm, &["Return", "Space"]),
+        (Action::Back, &["Back"]),
+        (Action::Quit, &["Escape"]),
+        (Action::Options, &["O"]),
+        (Action::Edit, &["E"]),
+        (Action::Playtest, &["Tab"]),
+        (Action::PreviousTile, &["Z"]),
+        (Action::NextTile, &["X"]),
+        (Action::PlaceTile, &["Space"]),
+        (Action::EraseTile, &["Delete", "Back"]),
+        (Action::PickTile, &["C"]),
+        (Action::PreviousLevel, &["PageUp"]),
+        (Action::NextLevel, &["PageDown"]),
+        (Action::Save, &["S"])
+    ];
+    bindings.iter().map(|&(action, keys)| (action, keys.iter().map(|key| key.to_string()).collect())).collect()
+}
+
+/// The name a key goes by in the bindings.
+pub fn key_name(key: VirtualKeyCode) -> String
+{
+    format!("{:?}", key)
+}
+
+/// Every action `key` is bound to.
+pub fn actions_for(bindings: &Bindings, key: VirtualKeyCode) -> Vec<Action>
+{
+    let name = key_name(key);
+    bindings.iter()
+        .filter(|&(_, keys)| keys.iter().any(|bound| bound.eq_ignore_ascii_case(&name)))
+        .map(|(action, _)| *action)
+        .collect()
+}
+
+/// Actions the menus can't be got round without, which always keep a key.
+pub const ESSENTIAL_ACTIONS: [Action; 4] = [Action::Back, Action::Confirm, Action::MoveUp, Action::MoveDown];
+
+/// Binds `key` to `action`, or unbinds it if it already was. Returns false,
+/// changing nothing, rather than take the last key from an essential action.
+pub fn toggle_binding(bindings: &mut Bindings, action: Action, key: VirtualKeyCode) -> bool
+{
+    let name = key_name(key);
+    let keys = bindings.entry(action).or_default();
+    match keys.iter().position(|bound| *bound == name)
+    {
+        Some(_) if keys.len() == 1 && ESSENTIAL_ACTIONS.contains(&action) => return false,
+        Some(index) => { keys.remove(index); },
+        None => keys.push(name)
+    }
+    true
+}
+
+/// This frame's input. Holding a key down makes it repeat, which menus use
+/// to keep moving while the game only cares about the first press.
+#[derive(Default)]
+pub struct Input
+{
+    /// Actions whose key went down this frame, in order.
+    pressed: Vec<Action>,
+    /// Actions whose key was already down and repeated this frame.
+    repeated: Vec<Action>,
+    released: Vec<Action>,
+    held_keys: HashSet<VirtualKeyCode>,
+    held: Vec<Action>,
+    /// The raw keys pressed this frame, for binding them to actions.
+    pub keys_pressed: Vec<VirtualKeyCode>,
+    pub any_key_pressed: bool
+}
+
+impl Input
+{
+    /// Forgets the last frame's presses and releases, keeping what's held.
+    pub fn start_frame(&mut self)
+    {
+        self.pressed.clear();
+        self.repeated.clear();
+        self.released.clear();
+        self.keys_pressed.clear();
+        self.any_key_pressed = false;
+    }
+
+    pub fn handle_key(&mut self, state: ElementState, key: VirtualKeyCode, bindings: &Bindings)
+    {
+        let actions = actions_for(bindings, key);
+        match state
+        {
+            ElementState::Pressed if self.held_keys.contains(&key) => self.repeated.extend(actions),
+            ElementState::Pressed =>
+            {
+                self.held_keys.insert(key);
+                self.keys_pressed.push(key);
+                self.any_key_pressed = true;
+                self.pressed.extend(actions);
+            },
+            ElementState::Released =>
+            {
+                self.held_keys.remove(&key);
+                self.released.extend(actions);
+            }
+        }
+
+        let held_keys = &self.held_keys;
+        self.held = bindings.iter()
+            .filter(|&(_, keys)| held_keys.iter().any(|key| keys.iter().any(|bound| bound.eq_ignore_ascii_case(&key_name(*key)))))
+            .map(|(action, _)| *action)
+            .collect();
+    }
+
+    pub fn pressed(&self, action: Action) -> bool
+    {
+        self.pressed.contains(&action)
+    }
+
+    /// Pressed, or held long enough to repeat.
+    pub fn pressed_or_repeated(&self, action: Action) -> bool
+    {
+        self.pressed(action) || self.repeated.contains(&action)
+    }
+
+    pub fn released(&self, action: Action) -> bool
+    {
+        self.released.contains(&action)
+    }
+
+    pub fn held(&self, action: Action) -> bool
+    {
+        self.held.contains(&action)
+    }
+
+    /// Every action freshly pressed this frame, in order.
+    pub fn presses(&self) -> &[Action]
+    {
+        &self.pressed
+    }
+
+    /// Every action pressed or repeated this frame, in order, for states
+    /// that handle several in one frame.
+    pub fn actions(&self) -> Vec<Action>
+    {
+        self.pressed.iter().chain(self.repeated.iter()).cloned().collect()
+    }
+
+    pub fn dx(&self) -> f32
+    {
+        if self.held(Action::MoveLeft) { -1.0 }
+        else if self.held(Action::MoveRight) { 1.0 }
+        else { 0.0 }
+    }
+
+    pub fn dy(&self) -> f32
+    {
+        if self.held(Action::MoveDown) { -1.0 }
+        else if self.held(Action::MoveUp) { 1.0 }
+        else { 0.0 }
+    }
+
+    /// The direction the player is holding, preferring left and right.
+    pub fn dir(&self) -> Vector2<f32>
+    {
+        if self.dx() != 0.0 { vec2(self.dx(), 0.0) }
+        else { vec2(0.0, self.dy()) }
+    }
+}
+
+/// Moves pressed while the player is still busy with the last one, to be
+/// made one at a time once they're free. Only a few are kept, so mashing a
+/// key doesn't leave the player walking long after letting go.
+pub struct MoveQueue
+{
+    moves: VecDeque<Vector2<f32>>,
+    depth: usize
+}
+
+impl MoveQueue
+{
+    pub fn new(depth: usize) -> Self
+    {
+        MoveQueue { moves: VecDeque::new(), depth: depth }
+    }
+
+    /// Queues a move, unless the queue is full.
+    pub fn push(&mut self, dir: Vector2<f32>)
+    {
+        if self.moves.len() < self.depth
+        {
+            self.moves.push_back(dir);
+        }
+    }
+
+    /// The next move to make, once the player is free to: the oldest queued
+    /// one, or else whichever way is being held.
+    pub fn next(&mut self, held_dir: Vector2<f32>) -> Vector2<f32>
+    {
+        self.moves.pop_front().unwrap_or(held_dir)
+    }
+
+    pub fn clear(&mut self)
+    {
+        self.moves.clear();
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    pub fn presses_repeats_and_releases()
+    {
+        let mut bindings = default_bindings();
+        bindings.get_mut(&Action::MoveLeft).unwrap().push("A".to_string());
+        let mut input = Input::default();
+
+        input.handle_key(ElementState::Pressed, VirtualKeyCode::A, &bindings);
+        assert!(input.pressed(Action::MoveLeft));
+        assert_eq!(input.dir(), vec2(-1.0, 0.0));
+
+        input.start_frame();
+        input.handle_key(ElementState::Pressed, VirtualKeyCode::A, &bindings);
+        assert!(!input.pressed(Action::MoveLeft));
+        assert!(input.pressed_or_repeated(Action::MoveLeft));
+
+        // Two keys for the same action: letting go of one keeps it held.
+        input.handle_key(ElementState::Pressed, VirtualKeyCode::Left, &bindings);
+        input.handle_key(ElementState::Released, VirtualKeyCode::A, &bindings);
+        assert!(input.released(Action::MoveLeft));
+        assert!(input.held(Action::MoveLeft));
+
+        input.start_frame();
+        input.handle_key(ElementState::Released, VirtualKeyCode::Left, &bindings);
+        assert_eq!(input.dir(), vec2(0.0, 0.0));
+
+        input.handle_key(ElementState::Pressed, VirtualKeyCode::Back, &bindings);
+        assert!(input.pressed(Action::Back) && input.pressed(Action::EraseTile));
+    }
+
+    #[test]
+    pub fn essential_actions_keep_a_key()
+    {
+        let mut bindings = default_bindings();
+        assert!(!toggle_binding(&mut bindings, Action::Back, VirtualKeyCode::Back));
+        assert_eq!(bindings[&Action::Back], vec!["Back"]);
+
+        assert!(toggle_binding(&mut bindings, Action::Confirm, VirtualKeyCode::Space));
+        assert!(!toggle_binding(&mut bindings, Action::Confirm, VirtualKeyCode::Return));
+        assert!(toggle_binding(&mut bindings, Action::Quit, VirtualKeyCode::Escape));
+        assert!(bindings[&Action::Quit].is_empty());
+    }
+
+    #[test]
+    pub fn queued_moves_come_first()
+    {
+        let mut queue = MoveQueue::new(2);
+        for action in &[Action::MoveUp, Action::MoveLeft, Action::MoveDown]
+        {
+            queue.push(action.direction().unwrap());
+        }
+
+        let held = vec2(1.0, 0.0);
+        assert_eq!(queue.next(held), vec2(0.0, 1.0));
+        assert_eq!(queue.next(held), vec2(-1.0, 0.0));
+        assert_eq!(queue.next(held), held);
+    }
+}
@@ -0,0 +1,173 @@
+
+use game::Game;
+use input::Action;
+use progress::MAX_STARS;
+use rendering::*;
+use state::*;
+use systems::visual::{BG_LAYER, OBJECT_LAYER, FG_LAYER, UI_LAYER};
+use vectors::*;
+
+const SLOT_REGION: Vector2<u32> = Vector2 { components: [0, 6] };
+const LOCK_REGION: Vector2<u32> = Vector2 { components: [5, 7] };
+const CURSOR_REGION: Vector2<u32> = Vector2 { components: [6, 7] };
+/// Regions showing one, two and three stars.
+const STAR_REGIONS: [Vector2<u32>; MAX_STARS as usize] = [Vector2 { components: [1, 6] }, Vector2 { components: [2, 6] }, Vector2 { components: [3, 6] }];
+
+const COLUMNS: usize = 8;
+const VISIBLE_ROWS: usize = 4;
+const SPACING: f32 = 2.0;
+
+/// Every level in the pack as a grid of slots, marked with the stars earned
+/// or locked until the level before is finished. Arrows choose a level, Enter
+/// or Space plays it, O opens the options and Backspace goes back to the
+/// packs. The chosen level's name and best result are shown above and below
+/// the grid, and in the window title.
+pub struct LevelSelectState
+{
+    display: Display,
+    renderer: SpriteRenderer,
+    text: TextRenderer,
+    selected: usize,
+    title: String
+}
+
+impl State for LevelSelectState
+{
+    fn new(display: &Display, game: &mut Game) -> Self
+    {
+        LevelSelectState
+        {
+            display: display.clone(),
+            renderer: SpriteRenderer::new(display),
+            text: TextRenderer::new(display),
+            selected: game.current_level,
+            title: String::new()
+        }
+    }
+
+    fn update(&mut self, _dt: f64, game: &mut Game) -> bool
+    {
+        let count = game.levels.len();
+        self.selected = self.selected.min(count - 1);
+        let mut exiting_state = false;
+
+        for action in game.input.actions()
+        {
+            match action
+            {
+                Action::MoveLeft if self.selected > 0 => self.selected -= 1,
+                Action::MoveRight if self.selected + 1 < count => self.selected += 1,
+                Action::MoveUp if self.selected >= COLUMNS => self.selected -= COLUMNS,
+                Action::MoveDown => self.selected = (self.selected + COLUMNS).min(count - 1),
+                Action::Confirm if game.level_unlocked(self.selected) =>
+                {
+                    game.current_level = self.selected;
+                    game.current_state = StateType::GameState;
+                    exiting_state = true;
+                },
+                Action::Options =>
+                {
+                    game.current_level = self.selected;
+                    game.current_state = StateType::Options;
+                    exiting_state = true;
+                },
+                Action::Back if game.packs.len() > 1 =>
+                {
+                    game.current_state = StateType::PackSelect;
+                    exiting_state = true;
+                },
+                _ => ()
+            }
+        }
+
+        let title = if exiting_state { "Small World".to_string() } else { self.describe_selection(game) };
+        if title != self.title
+        {
+            set_window_title(&self.display, &title);
+            self.title = title;
+        }
+
+        !exiting_state
+    }
+
+    fn draw(&mut self, target: &mut Canvas, game: &mut Game)
+    {
+        let palette = game.settings.palette();
+        target.clear_color_srgb_and_depth(palette.background, 1.0);
+
+        let mut sprites = Vec::new();
+        for index in 0..game.levels.len()
+        {
+            let position = slot_position(index);
+            sprites.push(SpriteInstance { layer: BG_LAYER, position: position, region: SLOT_REGION });
+
+            let mark = match game.progress.best(&game.level_key_at(index))
+            {
+                Some(result) if result.stars > 0 => Some(STAR_REGIONS[(result.stars.min(MAX_STARS) - 1) as usize]),
+                _ if !game.level_unlocked(index) => Some(LOCK_REGION),
+                _ => None
+            };
+            if let Some(region) = mark
+            {
+                sprites.push(SpriteInstance { layer: OBJECT_LAYER, position: position, region: region });
+            }
+        }
+        sprites.push(SpriteInstance { layer: FG_LAYER, position: slot_position(self.selected), region: CURSOR_REGION });
+
+        let projection = calculate_projection(game.resolution, game.tile_size);
+        self.renderer.draw(target, sprites, projection, self.camera_pos(game.levels.len()), game.tile_size, palette.tint);
+
+        let (width, height) = (game.resolution.0 as i32, game.resolution.1 as i32);
+        let texts = [
+            Text::new(format!("{}. {}", self.selected + 1, game.levels[self.selected].name), vec2(width / 2, height - 4), Align::Centre).wrapped(width as u32),
+            Text::new(self.describe_result(game), vec2(width / 2, 10), Align::Centre)
+        ];
+        self.text.draw(target, &texts, game.resolution, UI_LAYER, palette.text);
+    }
+
+    fn reload_graphics(&mut self, display: &Display, _game: &mut Game) -> Result<(), String>
+    {
+        self.renderer.reload(display)?;
+        self.text.reload(display)
+    }
+}
+
+impl LevelSelectState
+{
+    /// Centres the grid when it all fits, otherwise keeps the chosen row in
+    /// view.
+    fn camera_pos(&self, count: usize) -> Vector2<f32>
+    {
+        let rows = count.div_ceil(COLUMNS);
+        let row = if rows <= VISIBLE_ROWS { (rows as f32 - 1.0) * 0.5 } else { (self.selected / COLUMNS) as f32 };
+        vec2(0.0, -row * SPACING)
+    }
+
+    fn describe_selection(&self, game: &Game) -> String
+    {
+        let level = &game.levels[self.selected];
+        let title = format!("Small World - {} - {}. {}", game.pack.manifest.title, self.selected + 1, level.name);
+        match self.describe_result(game)
+        {
+            ref result if result.is_empty() => title,
+            result => format!("{} - {}", title, result)
+        }
+    }
+
+    /// The chosen level's best result, whether it's locked, or nothing.
+    fn describe_result(&self, game: &Game) -> String
+    {
+        match game.progress.best(&game.level_key_at(self.selected))
+        {
+            Some(result) => format!("{} of {} stars, {} moves", result.stars, MAX_STARS, result.moves),
+            None if game.level_unlocked(self.selected) => String::new(),
+            None => "locked".to_string()
+        }
+    }
+}
+
+fn slot_position(index: usize) -> Vector2<f32>
+{
+    let (column, row) = (index % COLUMNS, index / COLUMNS);
+    vec2((column as f32 - (COLUMNS as f32 - 1.0) * 0.5) * SPACING, -(row as f32) * SPACING)
+}
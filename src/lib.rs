@@ -1,137 +1,412 @@
+// The serde 0.9 derives put their impls inside a const, which newer
+// compilers warn about.
+#![allow(unknown_lints, non_local_definitions)]
+#![allow(clippy::redundant_field_names, clippy::expect_fun_call)]
+
 extern crate find_folder;
 #[macro_use] extern crate glium;
 extern crate image;
 #[macro_use] extern crate serde_derive;
+#[macro_use] extern crate serde_json;
 extern crate serde_yaml;
 
 extern crate specs;
+extern crate xml;
 
-use glium::{DisplayBuild};
-use glium::glutin::{Event, WindowBuilder, VirtualKeyCode, ElementState};
+use glium::{DisplayBuild, Rect, Surface};
+use glium::glutin::{get_primary_monitor, Event, WindowBuilder};
 
+use std::cmp::min;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::time::{Instant};
 
 pub mod assets;
+pub mod camera;
+pub mod editor_state;
 pub mod game;
 pub mod game_state;
+pub mod input;
+pub mod level_select_state;
 pub mod macros;
+pub mod options_state;
+pub mod pack_select_state;
+pub mod prefabs;
+pub mod progress;
 pub mod rendering;
+pub mod replay;
+pub mod save;
+pub mod settings;
+pub mod solver;
 pub mod splash_screen_state;
 pub mod state;
 pub mod systems;
 pub mod vectors;
 
+use editor_state::EditorState;
 use game::Game;
 use game_state::GameState;
+use level_select_state::LevelSelectState;
+use options_state::OptionsState;
+use pack_select_state::PackSelectState;
 use splash_screen_state::SplashScreenState;
-use rendering::Display;
+use assets::Level;
+use assets::packs::{self, Pack};
+use prefabs::PrefabTable;
+use rendering::{Align, Canvas, Display, Text, TextRenderer, Transition, TransitionKind, VirtualScreen, set_window_title};
+use rendering::font;
+use rendering::software;
+use solver::Solution;
+use input::Action;
+use systems::visual::UI_LAYER;
+use vectors::*;
+use settings::Overrides;
 use state::{State, StateType};
 
-pub fn run_game(scale: u32)
+/// How the game was asked to run, from the command line.
+#[derive(Clone, Debug, Default)]
+pub struct RunOptions
 {
-    let virtual_res = (256, 144);
-    let display = WindowBuilder::new()
+    pub overrides: Overrides,
+    /// A pack to play straight away, rather than offering them all.
+    pub pack: Option<PathBuf>,
+    /// A level to play straight away, by name or by number from 1.
+    pub level: Option<String>,
+    /// Moves to play back, which also pick the pack and level.
+    pub replay: Option<PathBuf>,
+    /// Where to record the moves made on each level finished, each level
+    /// to its own file named after this one.
+    pub record: Option<PathBuf>
+}
+
+/// The size of the game's screen, in game pixels.
+pub const VIRTUAL_RESOLUTION: (u32, u32) = (256, 144);
+
+/// The window the game opens, `size` pixels across unless it's fullscreen.
+/// Rebuilding the display from another one keeps everything loaded on it.
+pub fn window_builder(size: (u32, u32), fullscreen: bool, vsync: bool) -> WindowBuilder<'static>
+{
+    let mut builder = WindowBuilder::new()
         .with_title("Small World")
-        .with_dimensions(virtual_res.0 * scale, virtual_res.1 * scale)
-        .with_depth_buffer(24)
-        .with_vsync()
-        .build_glium()
-        .unwrap();
+        .with_dimensions(size.0, size.1);
+    if vsync
+    {
+        builder = builder.with_vsync();
+    }
+    if fullscreen
+    {
+        builder = builder.with_fullscreen(get_primary_monitor());
+    }
+    builder
+}
 
-    let mut game = Game::new(virtual_res);
+/// Runs the game with the player's settings, as overridden for this run.
+pub fn run_game(options: &RunOptions) -> Result<(), String>
+{
+    let virtual_res = VIRTUAL_RESOLUTION;
+    let settings_path = save::config_path(settings::SETTINGS_NAME);
+    let settings = settings_path.as_ref().map(|path| settings::load_settings(path)).unwrap_or_default();
+    let mut window_settings = settings.clone();
+    options.overrides.apply(&mut window_settings);
+    let window_size = options.overrides.window_size.unwrap_or((virtual_res.0 * window_settings.scale, virtual_res.1 * window_settings.scale));
+
+    let pack = match options.pack
+    {
+        Some(ref path) => Some(packs::load_pack(path)?),
+        None => None
+    };
+    let mut game = Game::new(virtual_res, pack, settings, settings_path)?;
+    game.record_path = options.record.clone();
+
+    let mut level = options.level.clone();
+    if let Some(ref path) = options.replay
+    {
+        let replay = replay::load_replay(path)?;
+        if options.pack.is_none() && replay.pack != game.pack.id
+        {
+            let pack = game.packs.iter().find(|pack| pack.id == replay.pack).cloned()
+                .ok_or(format!("No pack called '{}' for the replay", replay.pack))?;
+            game.load_pack(pack)?;
+        }
+        game.replay = Some(replay.dirs()?);
+        level = level.or(Some(replay.level));
+    }
+    if let Some(level) = level
+    {
+        game.current_level = assets::find_level(&game.levels, &level)
+            .ok_or(format!("No level '{}' in pack '{}'", level, game.pack.id))?;
+        game.current_state = StateType::GameState;
+    }
+
+    let display = window_builder(window_size, window_settings.fullscreen, window_settings.vsync).build_glium().unwrap();
+    let mut screen = VirtualScreen::new(&display, virtual_res);
 
     loop
     {
-        let keep_going = match game.current_state
+        let state = game.current_state;
+        let keep_going = match state
         {
-            StateType::SplashScreen => run_state::<SplashScreenState>(&display, &mut game),
-            StateType::EndingState => run_state::<SplashScreenState>(&display, &mut game),
-            StateType::GameState => run_state::<GameState>(&display, &mut game)
+            StateType::SplashScreen => run_state::<SplashScreenState>(&display, &mut screen, &mut game),
+            StateType::EndingState => run_state::<SplashScreenState>(&display, &mut screen, &mut game),
+            StateType::GameState => run_state::<GameState>(&display, &mut screen, &mut game),
+            StateType::Editor => run_state::<EditorState>(&display, &mut screen, &mut game),
+            StateType::PackSelect => run_state::<PackSelectState>(&display, &mut screen, &mut game),
+            StateType::LevelSelect => run_state::<LevelSelectState>(&display, &mut screen, &mut game),
+            StateType::Options => run_state::<OptionsState>(&display, &mut screen, &mut game)
         };
+        game.previous_state = state;
+        screen.keep_frame();
         if !keep_going
         {
             break;
         }
     }
+    game.save();
+    Ok(())
+}
+
+/// The most positions the verifier tries on each level before giving up.
+pub const VERIFY_MAX_STATES: usize = 100_000;
+
+/// Checks that every level in the pack given, or in every pack, can be
+/// finished within its par, printing what it finds instead of opening a
+/// window. With a replay, checks that the replay finishes its level instead.
+/// Returns false if anything fails, including levels too big to finish
+/// searching.
+pub fn verify(options: &RunOptions) -> bool
+{
+    let checked = prefabs::try_load_prefabs("entities.yaml").and_then(|prefabs|
+    {
+        let packs = match options.pack
+        {
+            Some(ref path) => vec![packs::load_pack(path)?],
+            None => packs::find_packs()?
+        };
+        match options.replay
+        {
+            Some(ref path) => verify_replay(path, &packs, &prefabs),
+            None =>
+            {
+                // Every pack gets checked, even after one fails.
+                let results: Vec<bool> = packs.iter().map(|pack| verify_pack(pack, options.level.as_deref(), &prefabs)).collect();
+                Ok(results.into_iter().all(|ok| ok))
+            }
+        }
+    });
+    checked.unwrap_or_else(|e|
+    {
+        println!("{}", e);
+        false
+    })
+}
+
+/// Draws how each level in the pack given, or in every pack, starts, into
+/// `<pack>-<number>.png` files in `folder`, without opening a window. The
+/// scale override makes the pictures bigger. Returns false if anything
+/// fails.
+pub fn export_thumbnails(options: &RunOptions, folder: &Path) -> bool
+{
+    let exported = prefabs::try_load_prefabs("entities.yaml").and_then(|prefabs|
+    {
+        let atlas = software::try_load_software_atlas()?;
+        let packs = match options.pack
+        {
+            Some(ref path) => vec![packs::load_pack(path)?],
+            None => packs::find_packs()?
+        };
+        fs::create_dir_all(folder).map_err(|e| format!("Could not create '{}': {}", folder.display(), e))?;
+
+        let palette = &settings::PALETTES[0];
+        let scale = options.overrides.scale.unwrap_or(1);
+        for pack in &packs
+        {
+            let levels = pack.try_load_levels(&prefabs).map_err(|e| format!("{}: {}", pack.id, e))?;
+            let levels: Vec<Level> = levels.into_iter().map(|(_, level)| level).collect();
+            let only_index = options.level.as_ref().map(|name| assets::find_level(&levels, name));
+            for (index, level) in levels.iter().enumerate()
+            {
+                if only_index.is_some() && only_index != Some(Some(index))
+                {
+                    continue;
+                }
+                let frame = game_state::render_level(level, &prefabs, &atlas, VIRTUAL_RESOLUTION, 16, palette);
+                let path = folder.join(format!("{}-{}.png", pack.id, index + 1));
+                software::write_png(&software::upscale(&frame, scale), &path)?;
+                println!("{}/{}: {}", pack.id, level.name, path.display());
+            }
+        }
+        Ok(())
+    });
+    exported.map(|_| true).unwrap_or_else(|e|
+    {
+        println!("{}", e);
+        false
+    })
+}
+
+fn verify_pack(pack: &Pack, only_level: Option<&str>, prefabs: &PrefabTable) -> bool
+{
+    let levels: Vec<Level> = match pack.try_load_levels(prefabs)
+    {
+        Ok(levels) => levels.into_iter().map(|(_, level)| level).collect(),
+        Err(e) =>
+        {
+            println!("{}: {}", pack.id, e);
+            return false;
+        }
+    };
+    let only_index = only_level.map(|name| assets::find_level(&levels, name));
+
+    let mut ok = true;
+    for (index, level) in levels.iter().enumerate()
+    {
+        if only_index.is_some() && only_index != Some(Some(index))
+        {
+            continue;
+        }
+
+        let name = format!("{}/{}", pack.id, level.name);
+        match solver::solve(level, prefabs, VERIFY_MAX_STATES)
+        {
+            Solution::Solved(dirs) =>
+            {
+                let moves: String = dirs.iter().map(|dir| replay::move_letter(*dir)).collect();
+                match level.meta.par
+                {
+                    Some(par) if (par as usize) < dirs.len() =>
+                    {
+                        println!("{}: par is {} but it takes at least {} moves ({})", name, par, dirs.len(), moves);
+                        ok = false;
+                    },
+                    _ => println!("{}: ok in {} moves ({})", name, dirs.len(), moves)
+                }
+            },
+            Solution::Unsolvable =>
+            {
+                println!("{}: can't be finished", name);
+                ok = false;
+            },
+            Solution::GaveUp(states) =>
+            {
+                println!("{}: gave up after {} positions, so it may not be finishable", name, states);
+                ok = false;
+            }
+        }
+    }
+    ok
 }
 
-pub fn run_state<S: State>(display: &Display, game: &mut Game) -> bool
+fn verify_replay(path: &Path, packs: &[Pack], prefabs: &PrefabTable) -> Result<bool, String>
+{
+    let replay = replay::load_replay(path)?;
+    let pack = packs.iter().find(|pack| pack.id == replay.pack).ok_or(format!("No pack called '{}' for the replay", replay.pack))?;
+    let levels: Vec<Level> = pack.try_load_levels(prefabs)?.into_iter().map(|(_, level)| level).collect();
+    let index = assets::find_level(&levels, &replay.level).ok_or(format!("No level '{}' in pack '{}'", replay.level, pack.id))?;
+
+    let finished = solver::check_moves(&levels[index], prefabs, &replay.dirs()?);
+    println!("{}/{}: the replay {}", pack.id, replay.level, if finished { "finishes it" } else { "doesn't finish it" });
+    Ok(finished)
+}
+
+pub fn run_state<S: State>(display: &Display, screen: &mut VirtualScreen, game: &mut Game) -> bool
 {
     let mut game_state = S::new(display, game);
+    let mut transition = game.transition.take().unwrap_or_else(|| Transition::new(TransitionKind::Fade));
+    let mut error_text = TextRenderer::new(display);
     let mut previous_frame_time = Instant::now();
+    let mut shown_error = None;
 
     loop
     {
         let current_time = Instant::now();
         let delta = current_time.duration_since(previous_frame_time);
-        let dt = (delta.as_secs() as f64) + (delta.subsec_nanos() as f64) / 1000_000_000.0;
+        let dt = (delta.as_secs() as f64) + (delta.subsec_nanos() as f64) / 1_000_000_000.0;
         previous_frame_time = current_time;
 
         let mut quitting = false;
-        let mut reset_key_pressed = false;
-        let mut next_level_key_pressed = false;
-        let mut any_key_pressed = false;
+        game.input.start_frame();
 
+        for event in display.poll_events()
         {
-            for event in display.poll_events()
+            match event
             {
-                match event
-                {
-                    Event::KeyboardInput(state, _, Some(key)) =>
-                        match state
-                        {
-                            ElementState::Pressed =>
-                            {
-                                any_key_pressed = true;
-                                match key
-                                {
-                                    VirtualKeyCode::Left => game.input.left = true,
-                                    VirtualKeyCode::Right => game.input.right = true,
-                                    VirtualKeyCode::Up => game.input.up = true,
-                                    VirtualKeyCode::Down => game.input.down = true,
-                                    VirtualKeyCode::R => reset_key_pressed = true,
-                                    VirtualKeyCode::N => next_level_key_pressed = true,
-                                    VirtualKeyCode::Escape => quitting = true,
-                                    _ => ()
-                                }
-                            },
-                            ElementState::Released =>
-                                match key
-                                {
-                                    VirtualKeyCode::Left => game.input.left = false,
-                                    VirtualKeyCode::Right => game.input.right = false,
-                                    VirtualKeyCode::Up => game.input.up = false,
-                                    VirtualKeyCode::Down => game.input.down = false,
-                                    _ => ()
-                                }
-                        },
-                    Event::Closed => quitting = true,
-                    _ => ()
-                }
+                Event::KeyboardInput(state, _, Some(key)) => game.input.handle_key(state, key, &game.settings.keys),
+                Event::Closed => quitting = true,
+                _ => ()
             }
-
-            game.input.any_key_pressed = any_key_pressed;
         }
 
-        if cfg!(debug_assertions)
+        let mut reset_key_pressed = game.input.pressed(Action::Restart);
+        let mut next_level_key_pressed = game.input.pressed(Action::Skip);
+
+        // The editor has its own uses for these keys, and reloading would
+        // throw away its changes. The options menu may be rebinding them.
+        if game.current_state == StateType::Editor || game.current_state == StateType::Options
         {
-            if next_level_key_pressed
-            {
-                game.current_level += 1;
-            }
+            reset_key_pressed = false;
+            next_level_key_pressed = false;
+        }
+        quitting |= game.input.pressed(Action::Quit) && game.current_state != StateType::Options;
+        // Play-testing restarts the edited level rather than reloading the
+        // saved one over it.
+        if game.playtesting && (reset_key_pressed || next_level_key_pressed)
+        {
+            game.transition = Some(Transition::new(TransitionKind::Wipe));
+            return true;
+        }
+        if cfg!(debug_assertions) && next_level_key_pressed
+        {
+            game.current_level += 1;
         }
         if reset_key_pressed || next_level_key_pressed
         {
-            game.levels = assets::load_levels("levels.yaml");
+            game.level_error = game.reload_levels().err();
+            game.transition = Some(Transition::new(TransitionKind::Wipe));
             return true;
         }
 
+        let mut restarting = false;
+        let changed_files = game.watcher.poll();
+        if changed_files.iter().any(|path| !game.is_level_file(path))
+        {
+            game.graphics_error = game_state.reload_graphics(display, game)
+                .and_then(|_| screen.reload(display))
+                .and_then(|_| error_text.reload(display)).err();
+        }
+        // Levels being edited or play-tested aren't reloaded over.
+        if changed_files.iter().any(|path| game.is_level_file(path)) && game.current_state != StateType::Editor && !game.playtesting
+        {
+            game.level_error = game.reload_levels().err();
+            restarting = game.level_error.is_none();
+        }
+
+        let error = game.error_message();
+        if error != shown_error
+        {
+            show_error(display, &error);
+            shown_error = error;
+        }
+
         let state_continue = game_state.update(dt, game);
+        transition.update(dt);
 
         let mut target = display.draw();
-        game_state.draw(&mut target, game);
+        {
+            let mut canvas = screen.canvas(display);
+            game_state.draw(&mut canvas, game);
+            if let Some(ref error) = shown_error
+            {
+                draw_error(&mut canvas, &mut error_text, error, screen.resolution);
+            }
+        }
+        screen.present(&mut target, Some(&transition));
         target.finish().expect("Drawing failed");
 
+        if restarting
+        {
+            return true;
+        }
+
         if quitting
         {
             return false;
@@ -143,3 +418,35 @@ pub fn run_state<S: State>(display: &Display, game: &mut Game) -> bool
         }
     }
 }
+
+/// Reload errors go in the console, where they can be copied, and the
+/// window title.
+fn show_error(display: &Display, error: &Option<String>)
+{
+    let title = match *error
+    {
+        Some(ref error) =>
+        {
+            eprintln!("{}", error);
+            format!("Small World - {}", error.lines().next().unwrap_or(""))
+        },
+        None => "Small World".to_string()
+    };
+    set_window_title(display, &title);
+}
+
+/// The whole error, wrapped to fit on a dark red panel over the screen. The
+/// panel's cleared rather than drawn, in case it's the shaders that broke.
+fn draw_error(canvas: &mut Canvas, text: &mut TextRenderer, error: &str, resolution: (u32, u32))
+{
+    const MARGIN: u32 = 4;
+    let (width, height) = resolution;
+    let inner = width - MARGIN * 4;
+    let lines = font::wrap(&text.metrics, error, Some(inner)).len() as u32;
+    let panel_height = min(lines * text.metrics.line_height + MARGIN * 2, height - MARGIN * 2);
+    let rect = Rect { left: MARGIN, bottom: height - MARGIN - panel_height, width: width - MARGIN * 2, height: panel_height };
+    canvas.clear(Some(&rect), Some((0.5, 0.05, 0.05, 1.0)), true, Some(1.0), None);
+
+    let message = Text::new(error, vec2((MARGIN * 2) as i32, (height - MARGIN * 2) as i32), Align::Left).wrapped(inner);
+    text.draw(canvas, &[message], resolution, UI_LAYER, [1.0, 1.0, 1.0, 1.0]);
+}
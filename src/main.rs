@@ -1,6 +1,106 @@
 extern crate smallworld;
 
+use std::env;
+use std::path::PathBuf;
+use std::process::exit;
+
+use smallworld::assets;
+use smallworld::RunOptions;
+
+const USAGE: &str = "\
+Usage:
+    smallworld [options]
+
+Options:
+    --pack <dir>            Play the pack in <dir> (or the manifest at <dir>)
+    --level <name|number>   Start on a level, by name or by number from 1
+    --assets <dir>          Load assets from <dir>
+    --replay <file>         Play back the moves recorded in <file>
+    --record <file>         Record the moves made on each level finished, to
+                            <file>-<pack>-<level number> beside <file>
+    --scale <n>             Make each pixel <n> pixels across
+    --windowed-size <WxH>   Open a window <W> by <H> pixels, whatever the scale
+    --fullscreen            Play fullscreen
+    --windowed              Play in a window
+    --no-vsync              Don't wait for vsync
+    --headless-verify       Check every level (or the replay) can be finished, and exit
+    --export-thumbnails <dir>
+                            Save a picture of how each level starts to <dir>, and exit
+    --help                  Show this";
+
 fn main()
 {
-    smallworld::run_game(4);
+    let mut options = RunOptions::default();
+    let mut verify = false;
+    let mut thumbnails = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next()
+    {
+        let mut value = |name: &str| args.next().unwrap_or_else(|| fail(&format!("{} needs a value", name)));
+        match arg.as_str()
+        {
+            "--pack" => options.pack = Some(from_working_dir(value("--pack"))),
+            "--level" => options.level = Some(value("--level")),
+            "--assets" => assets::set_assets_folder(from_working_dir(value("--assets"))),
+            "--replay" => options.replay = Some(from_working_dir(value("--replay"))),
+            "--record" => options.record = Some(from_working_dir(value("--record"))),
+            "--scale" =>
+            {
+                let scale = value("--scale");
+                options.overrides.scale = Some(scale.parse().unwrap_or_else(|_| fail(&format!("'{}' isn't a scale", scale))));
+            },
+            "--windowed-size" =>
+            {
+                let size = value("--windowed-size");
+                options.overrides.window_size = Some(parse_size(&size).unwrap_or_else(|| fail(&format!("'{}' isn't a size like 1280x720", size))));
+                options.overrides.fullscreen = Some(false);
+            },
+            "--fullscreen" => options.overrides.fullscreen = Some(true),
+            "--windowed" => options.overrides.fullscreen = Some(false),
+            "--no-vsync" => options.overrides.vsync = Some(false),
+            "--headless-verify" => verify = true,
+            "--export-thumbnails" => thumbnails = Some(from_working_dir(value("--export-thumbnails"))),
+            "--help" | "-h" =>
+            {
+                println!("{}", USAGE);
+                return;
+            },
+            _ => fail(&format!("Unknown argument '{}'", arg))
+        }
+    }
+
+    if let Some(folder) = thumbnails
+    {
+        exit(if smallworld::export_thumbnails(&options, &folder) { 0 } else { 1 });
+    }
+    if verify
+    {
+        exit(if smallworld::verify(&options) { 0 } else { 1 });
+    }
+    if let Err(e) = smallworld::run_game(&options)
+    {
+        fail(&e);
+    }
+}
+
+fn fail(message: &str) -> !
+{
+    eprintln!("{}\n\n{}", message, USAGE);
+    exit(2);
+}
+
+fn from_working_dir(path: String) -> PathBuf
+{
+    env::current_dir().expect("Could not find working directory").join(path)
+}
+
+fn parse_size(size: &str) -> Option<(u32, u32)>
+{
+    let mut parts = size.split('x');
+    match (parts.next().and_then(|w| w.parse().ok()), parts.next().and_then(|h| h.parse().ok()), parts.next())
+    {
+        (Some(width), Some(height), None) if width > 0 && height > 0 => Some((width, height)),
+        _ => None
+    }
 }
@@ -0,0 +1,282 @@
+use glium::DisplayBuild;
+
+use game::Game;
+use input::{toggle_binding, Action, ACTIONS};
+use rendering::*;
+use settings::{Settings, PALETTES};
+use state::*;
+use systems::visual::{BG_LAYER, FG_LAYER, UI_LAYER};
+use vectors::*;
+
+const SLOT_REGION: Vector2<u32> = Vector2 { components: [0, 6] };
+const CURSOR_REGION: Vector2<u32> = Vector2 { components: [6, 7] };
+const WAITING_REGION: Vector2<u32> = Vector2 { components: [5, 7] };
+
+const VISIBLE_ROWS: usize = 6;
+const SPACING: f32 = 1.5;
+/// How far in from the left of the screen the slots are, in tiles.
+const SLOT_X: f32 = 1.0;
+/// The gap between each slot and its setting's name, and the right of the
+/// screen and its value, in pixels.
+const TEXT_MARGIN: i32 = 12;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Row
+{
+    Scale,
+    Fullscreen,
+    Vsync,
+    Volume,
+    AnimationSpeed,
+    MoveBuffer,
+    Palette,
+    Hud,
+    Keys(Action)
+}
+
+/// Changes the settings. Up and down choose a setting and left and right
+/// change it. Confirming an action waits for the next key pressed, and
+/// binds it to the action, or unbinds it if it already was, unless it's the
+/// last key of an action the menus need. Backspace goes back, saving any
+/// changes. Each setting's value is shown beside it, and the chosen one's in
+/// the window title too.
+pub struct OptionsState
+{
+    display: Display,
+    renderer: SpriteRenderer,
+    text: TextRenderer,
+    rows: Vec<Row>,
+    selected: usize,
+    /// Whether the next key pressed gets bound to the selected action.
+    rebinding: bool,
+    /// The settings as they were, to tell whether there's anything to save.
+    original: Settings,
+    return_state: StateType,
+    title: String
+}
+
+impl State for OptionsState
+{
+    fn new(display: &Display, game: &mut Game) -> Self
+    {
+        let mut rows = vec![Row::Scale, Row::Fullscreen, Row::Vsync, Row::Volume, Row::AnimationSpeed, Row::MoveBuffer, Row::Palette, Row::Hud];
+        rows.extend(ACTIONS.iter().map(|action| Row::Keys(*action)));
+
+        OptionsState
+        {
+            display: display.clone(),
+            renderer: SpriteRenderer::new(display),
+            text: TextRenderer::new(display),
+            rows: rows,
+            selected: 0,
+            rebinding: false,
+            original: game.settings.clone(),
+            return_state: game.previous_state,
+            title: String::new()
+        }
+    }
+
+    fn update(&mut self, _dt: f64, game: &mut Game) -> bool
+    {
+        let mut exiting_state = false;
+
+        if self.rebinding
+        {
+            if let (Some(&key), Row::Keys(action)) = (game.input.keys_pressed.first(), self.rows[self.selected])
+            {
+                toggle_binding(&mut game.settings.keys, action, key);
+                self.rebinding = false;
+            }
+        }
+        else
+        {
+            exiting_state = self.handle_actions(game);
+        }
+
+        let title = if exiting_state { "Small World".to_string() } else { self.describe_selection(game) };
+        if title != self.title
+        {
+            set_window_title(&self.display, &title);
+            self.title = title;
+        }
+
+        !exiting_state
+    }
+
+    fn draw(&mut self, target: &mut Canvas, game: &mut Game)
+    {
+        let palette = game.settings.palette();
+        target.clear_color_srgb_and_depth(palette.background, 1.0);
+
+        let mut sprites: Vec<SpriteInstance> = (0..self.rows.len())
+            .map(|index| SpriteInstance { layer: BG_LAYER, position: row_position(index), region: SLOT_REGION })
+            .collect();
+        let cursor_region = if self.rebinding { WAITING_REGION } else { CURSOR_REGION };
+        sprites.push(SpriteInstance { layer: FG_LAYER, position: row_position(self.selected), region: cursor_region });
+
+        let projection = calculate_projection(game.resolution, game.tile_size);
+        let camera_pos = self.camera_pos(game);
+        self.renderer.draw(target, sprites, projection, camera_pos, game.tile_size, palette.tint);
+
+        // Each row's name beside its slot, and its value on the right.
+        let (width, height) = (game.resolution.0 as i32, game.resolution.1 as i32);
+        let glyph_height = self.text.metrics.cell[1] as i32;
+        let mut texts = Vec::new();
+        for (index, row) in self.rows.iter().enumerate()
+        {
+            let offset = (row_position(index) - camera_pos) * game.tile_size as f32;
+            let centre = vec2(width / 2 + offset.components[0] as i32, height / 2 + offset.components[1] as i32);
+            if centre.components[1] < -glyph_height || centre.components[1] > height + glyph_height
+            {
+                continue;
+            }
+            let top = centre.components[1] + glyph_height / 2;
+            let (label, value) = self.describe_row(game, *row, index == self.selected);
+            texts.push(Text::new(label, vec2(centre.components[0] + TEXT_MARGIN, top), Align::Left));
+            texts.push(Text::new(value, vec2(width - TEXT_MARGIN, top), Align::Right));
+        }
+        self.text.draw(target, &texts, game.resolution, UI_LAYER, palette.text);
+    }
+
+    fn reload_graphics(&mut self, display: &Display, _game: &mut Game) -> Result<(), String>
+    {
+        self.renderer.reload(display)?;
+        self.text.reload(display)
+    }
+}
+
+impl OptionsState
+{
+    /// Returns true when leaving the options.
+    fn handle_actions(&mut self, game: &mut Game) -> bool
+    {
+        for action in game.input.actions()
+        {
+            match action
+            {
+                Action::MoveUp if self.selected > 0 => self.selected -= 1,
+                Action::MoveDown if self.selected + 1 < self.rows.len() => self.selected += 1,
+                Action::MoveLeft => self.change(game, -1),
+                Action::MoveRight => self.change(game, 1),
+                Action::Confirm =>
+                {
+                    match self.rows[self.selected]
+                    {
+                        Row::Keys(_) => self.rebinding = true,
+                        _ => self.change(game, 1)
+                    }
+                },
+                Action::Back =>
+                {
+                    if game.settings != self.original
+                    {
+                        game.save_settings();
+                    }
+                    game.current_state = self.return_state;
+                    return true;
+                },
+                _ => ()
+            }
+        }
+        false
+    }
+
+    /// Steps the selected setting up or down, taking effect straight away.
+    fn change(&mut self, game: &mut Game, step: i32)
+    {
+        let settings = &mut game.settings;
+        match self.rows[self.selected]
+        {
+            Row::Scale =>
+            {
+                settings.scale = (settings.scale as i32 + step).max(0) as u32;
+                settings.sanitise();
+                if let Some(window) = self.display.get_window()
+                {
+                    window.set_inner_size(game.resolution.0 * settings.scale, game.resolution.1 * settings.scale);
+                }
+            },
+            Row::Fullscreen => settings.fullscreen = !settings.fullscreen,
+            Row::Vsync => settings.vsync = !settings.vsync,
+            Row::Volume => settings.volume += 0.1 * step as f32,
+            Row::AnimationSpeed => settings.animation_speed += 0.25 * step as f32,
+            Row::MoveBuffer => settings.move_buffer = (settings.move_buffer as i32 + step).max(0) as usize,
+            Row::Palette =>
+            {
+                let current = PALETTES.iter().position(|palette| palette.name == settings.palette().name).unwrap_or(0);
+                let next = (current as i32 + step).rem_euclid(PALETTES.len() as i32) as usize;
+                settings.palette = PALETTES[next].name.to_string();
+            },
+            Row::Hud => settings.show_hud = !settings.show_hud,
+            Row::Keys(_) => ()
+        }
+        settings.sanitise();
+
+        if let Row::Fullscreen | Row::Vsync = self.rows[self.selected]
+        {
+            self.rebuild_window(game);
+        }
+    }
+
+    /// Opens the window again with the new fullscreen and vsync settings,
+    /// keeping everything loaded on it.
+    fn rebuild_window(&mut self, game: &Game)
+    {
+        let settings = &game.settings;
+        let size = (game.resolution.0 * settings.scale, game.resolution.1 * settings.scale);
+        if let Err(e) = ::window_builder(size, settings.fullscreen, settings.vsync).rebuild_glium(&self.display)
+        {
+            eprintln!("Could not change the window: {:?}", e);
+        }
+        // The new window starts with the default title.
+        self.title.clear();
+    }
+
+    /// Keeps the selected row in view, with the slots down the left.
+    fn camera_pos(&self, game: &Game) -> Vector2<f32>
+    {
+        let row = self.selected.max(VISIBLE_ROWS / 2).min(self.rows.len() - VISIBLE_ROWS / 2) as f32 - 0.5;
+        let half_width = game.resolution.0 as f32 * 0.5 / game.tile_size as f32;
+        vec2(half_width - 0.5 - SLOT_X, -row * SPACING)
+    }
+
+    /// A row's setting and its value, or what to do if it's `selected` and
+    /// waiting for a key.
+    fn describe_row(&self, game: &Game, row: Row, selected: bool) -> (String, String)
+    {
+        let settings = &game.settings;
+        let on_off = |on: bool| if on { "on" } else { "off" }.to_string();
+        match row
+        {
+            Row::Scale => ("Scale".to_string(), format!("{}x", settings.scale)),
+            Row::Fullscreen => ("Fullscreen".to_string(), on_off(settings.fullscreen)),
+            Row::Vsync => ("Vsync".to_string(), on_off(settings.vsync)),
+            Row::Volume => ("Volume".to_string(), format!("{}%", (settings.volume * 100.0).round())),
+            Row::AnimationSpeed => ("Animation speed".to_string(), format!("{}x", settings.animation_speed)),
+            Row::MoveBuffer => ("Moves remembered while moving".to_string(), settings.move_buffer.to_string()),
+            Row::Palette => ("Palette".to_string(), settings.palette().name.to_string()),
+            Row::Hud => ("HUD".to_string(), on_off(settings.show_hud)),
+            Row::Keys(action) if selected && self.rebinding => (format!("Keys for {}", action.name()), "press a key".to_string()),
+            Row::Keys(action) => (format!("Keys for {}", action.name()), settings.keys.get(&action).map_or(String::new(), |keys| keys.join(", ")))
+        }
+    }
+
+    fn describe_selection(&self, game: &Game) -> String
+    {
+        let description = match (self.rows[self.selected], self.rebinding)
+        {
+            (Row::Keys(action), true) => format!("Press a key to add to or remove from {}", action.name()),
+            (row, _) =>
+            {
+                let (label, value) = self.describe_row(game, row, true);
+                format!("{}: {}", label, value)
+            }
+        };
+        format!("Small World options - {}", description)
+    }
+}
+
+fn row_position(index: usize) -> Vector2<f32>
+{
+    vec2(0.0, -(index as f32) * SPACING)
+}
@@ -0,0 +1,180 @@
+use specs::World;
+
+use game::Game;
+use game_state::{build_world, world_sprites};
+use input::Action;
+use rendering::*;
+use state::*;
+use systems::visual::{FG_LAYER, UI_LAYER};
+use vectors::*;
+
+const LOCK_REGION: Vector2<u32> = Vector2 { components: [5, 7] };
+
+/// Picks which level pack to play. Left and right choose a pack, showing its
+/// first level, and Enter or Space picks it if it's unlocked. O opens the
+/// options. The pack's details are shown over its level, and in the window
+/// title.
+pub struct PackSelectState
+{
+    display: Display,
+    renderer: SpriteRenderer,
+    text: TextRenderer,
+    previews: Vec<Result<(World, Vector2<f32>), String>>,
+    selected: usize,
+    title: String
+}
+
+impl State for PackSelectState
+{
+    fn new(display: &Display, game: &mut Game) -> Self
+    {
+        let renderer = SpriteRenderer::new(display);
+
+        let previews = game.packs.iter()
+            .map(|pack| pack.try_load_levels(&game.prefabs).map(|levels|
+            {
+                let level = &levels[0].1;
+                (build_world(level, &game.prefabs), level.midpoint)
+            }))
+            .collect();
+        let selected = game.packs.iter().position(|pack| pack.id == game.pack.id).unwrap_or(0);
+
+        PackSelectState
+        {
+            display: display.clone(),
+            renderer: renderer,
+            text: TextRenderer::new(display),
+            previews: previews,
+            selected: selected,
+            title: String::new()
+        }
+    }
+
+    fn update(&mut self, _dt: f64, game: &mut Game) -> bool
+    {
+        let count = game.packs.len();
+        let mut exiting_state = false;
+
+        for action in game.input.actions()
+        {
+            match action
+            {
+                Action::MoveLeft => self.selected = (self.selected + count - 1) % count,
+                Action::MoveRight => self.selected = (self.selected + 1) % count,
+                Action::Confirm =>
+                {
+                    let pack = game.packs[self.selected].clone();
+                    if pack.is_unlocked(&game.progress) && self.previews[self.selected].is_ok()
+                    {
+                        game.level_error = game.load_pack(pack).err();
+                        game.current_state = StateType::LevelSelect;
+                        exiting_state = game.level_error.is_none();
+                    }
+                },
+                Action::Options =>
+                {
+                    game.current_state = StateType::Options;
+                    exiting_state = true;
+                },
+                _ => ()
+            }
+        }
+
+        let title = if exiting_state
+        {
+            "Small World".to_string()
+        }
+        else
+        {
+            let (heading, status) = self.describe_selection(game);
+            let heading = heading.replace('\n', " ");
+            match status
+            {
+                Some(status) => format!("Small World - {} - {}", heading, status),
+                None => format!("Small World - {}", heading)
+            }
+        };
+        if title != self.title
+        {
+            set_window_title(&self.display, &title);
+            self.title = title;
+        }
+
+        !exiting_state
+    }
+
+    fn draw(&mut self, target: &mut Canvas, game: &mut Game)
+    {
+        let palette = game.settings.palette();
+        target.clear_color_srgb_and_depth(palette.background, 1.0);
+
+        if let Ok((ref world, camera_pos)) = self.previews[self.selected]
+        {
+            let mut sprites = world_sprites(world);
+            if !game.packs[self.selected].is_unlocked(&game.progress)
+            {
+                sprites.push(SpriteInstance { layer: FG_LAYER + 1, position: camera_pos, region: LOCK_REGION });
+            }
+
+            let projection = calculate_projection(game.resolution, game.tile_size);
+            self.renderer.draw(target, sprites, projection, camera_pos, game.tile_size, palette.tint);
+        }
+
+        let (width, height) = (game.resolution.0 as i32, game.resolution.1 as i32);
+        let (heading, status) = self.describe_selection(game);
+        let mut texts = vec![Text::new(heading, vec2(width / 2, height - 4), Align::Centre).wrapped(width as u32 - 8)];
+        if let Some(status) = status
+        {
+            let lines = font::wrap(&self.text.metrics, &status, Some(width as u32 - 8)).len() as u32;
+            let top = 4 + (lines * self.text.metrics.line_height) as i32;
+            texts.push(Text::new(status, vec2(width / 2, top), Align::Centre).wrapped(width as u32 - 8));
+        }
+        self.text.draw(target, &texts, game.resolution, UI_LAYER, palette.text);
+    }
+
+    fn reload_graphics(&mut self, display: &Display, _game: &mut Game) -> Result<(), String>
+    {
+        self.renderer.reload(display)?;
+        self.text.reload(display)
+    }
+}
+
+impl PackSelectState
+{
+    /// The pack's title and author, and why it can't be played if it
+    /// can't.
+    fn describe_selection(&self, game: &Game) -> (String, Option<String>)
+    {
+        let pack = &game.packs[self.selected];
+        let mut heading = format!("< {} >", pack.manifest.title);
+        if let Some(ref author) = pack.manifest.author
+        {
+            heading = format!("{}\nby {}", heading, author);
+        }
+
+        let status = if let Err(ref error) = self.previews[self.selected]
+        {
+            Some(format!("Broken: {}", error))
+        }
+        else if !pack.is_unlocked(&game.progress)
+        {
+            let rules = &pack.manifest.unlock;
+            let mut needs = Vec::new();
+            if let Some(ref after) = rules.after
+            {
+                let after_title = game.packs.iter().find(|p| p.id == *after).map_or(after.as_str(), |p| p.manifest.title.as_str());
+                needs.push(format!("finish {}", after_title));
+            }
+            if rules.stars > 0
+            {
+                needs.push(format!("{} of {} stars", game.progress.total_stars(), rules.stars));
+            }
+            Some(format!("Locked: {}", needs.join(", ")))
+        }
+        else
+        {
+            None
+        };
+        (heading, status)
+    }
+}
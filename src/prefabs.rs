@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::path::Path;
+use serde_yaml;
+use specs::World;
+
+use assets::{read_asset_string, CollisionData, Level, TileLayer};
+use systems::*;
+use systems::animation::{self, AnimationTable, ClipSet};
+use systems::particles::{self, ParticleEffect, ParticleTable, Trigger};
+use vectors::*;
+
+/// Everything placed by a single tile code in a level. Most prefabs are one
+/// entity, but some (like doors, which sit in front of a wall) are several.
+#[derive(Deserialize, Clone)]
+pub struct Prefab
+{
+    pub name: String,
+    #[serde(default)]
+    pub layer: Option<String>,
+    pub entities: Vec<EntityTemplate>
+}
+
+impl Prefab
+{
+    /// The level layer this prefab is placed on when a level doesn't say.
+    pub fn tile_layer(&self) -> TileLayer
+    {
+        match self.layer
+        {
+            Some(ref name) => TileLayer::from_name(name).expect(&format!("Unknown tile layer '{}'", name)),
+            None => TileLayer::Terrain
+        }
+    }
+}
+
+/// The components an entity is built with. Anything left out of the file is
+/// simply not attached.
+#[derive(Deserialize, Clone, Default)]
+pub struct EntityTemplate
+{
+    #[serde(default)]
+    pub sprite: Option<SpriteData>,
+    #[serde(default)]
+    pub motion: Option<MotionData>,
+    #[serde(default)]
+    pub collision: Option<CollisionData>,
+    #[serde(default)]
+    pub tracker: Option<TrackerData>,
+    #[serde(default)]
+    pub gate: Option<GateData>,
+    /// The name of a clip set in `animations.yaml`.
+    #[serde(default)]
+    pub animation: Option<String>,
+    /// The clips `animation` names, filled in once the prefabs are loaded.
+    #[serde(skip_deserializing)]
+    pub clips: Option<ClipSet>,
+    #[serde(default)]
+    pub emitter: Option<EmitterData>,
+    /// The effect `emitter` names, filled in once the prefabs are loaded.
+    #[serde(skip_deserializing)]
+    pub effect: Option<ParticleEffect>,
+    #[serde(default)]
+    pub player: bool,
+    #[serde(default)]
+    pub hazard: bool,
+    #[serde(default)]
+    pub goal: bool,
+    #[serde(default)]
+    pub button: bool,
+    #[serde(default)]
+    pub collectible: bool
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SpriteData
+{
+    pub region: [u32; 2],
+    pub layer: String
+}
+
+#[derive(Deserialize, Clone)]
+pub struct MotionData
+{
+    pub speed: f32
+}
+
+#[derive(Deserialize, Clone)]
+pub struct TrackerData
+{
+    pub delay: f32
+}
+
+#[derive(Deserialize, Clone)]
+pub struct GateData
+{
+    pub open_region: [u32; 2]
+}
+
+#[derive(Deserialize, Clone)]
+pub struct EmitterData
+{
+    /// The name of an effect in `particles.yaml`.
+    pub effect: String,
+    pub on: Trigger
+}
+
+#[derive(Deserialize, Default)]
+pub struct PrefabTable
+{
+    pub prefabs: HashMap<String, Prefab>
+}
+
+impl PrefabTable
+{
+    pub fn get(&self, code: &str) -> Option<&Prefab>
+    {
+        self.prefabs.get(code)
+    }
+
+    /// Every tile code, in a stable order.
+    pub fn codes(&self) -> Vec<String>
+    {
+        let mut codes: Vec<String> = self.prefabs.keys().cloned().collect();
+        codes.sort();
+        codes
+    }
+
+    pub fn has_component<F>(&self, code: &str, test: F) -> bool
+        where F: Fn(&EntityTemplate) -> bool
+    {
+        match self.get(code)
+        {
+            Some(prefab) => prefab.entities.iter().any(test),
+            None => false
+        }
+    }
+}
+
+
+/// Where the clips prefabs name are, next to the atlas they're drawn from.
+pub const ANIMATIONS_PATH: &str = "animations.yaml";
+/// Where the particle effects prefabs name are.
+pub const PARTICLES_PATH: &str = "particles.yaml";
+
+pub fn load_prefabs<P>(path: P) -> PrefabTable
+    where P: AsRef<Path>
+{
+    try_load_prefabs(path).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Loads prefabs without panicking, checking the names in them that would
+/// otherwise only blow up once something is spawned.
+pub fn try_load_prefabs<P>(path: P) -> Result<PrefabTable, String>
+    where P: AsRef<Path>
+{
+    let yaml = read_asset_string(path)?;
+    let table: PrefabTable = serde_yaml::from_str(&yaml).map_err(|e| format!("Failed to parse entity prefabs: {}", e))?;
+    let animations = animation::load_animations(ANIMATIONS_PATH)?;
+    let effects = particles::load_particles(PARTICLES_PATH)?;
+    resolve_prefabs(table, &animations, &effects)
+}
+
+/// Checks the layer names in `table`, and looks up the animations and
+/// particle effects its prefabs use.
+pub fn resolve_prefabs(mut table: PrefabTable, animations: &AnimationTable, effects: &ParticleTable) -> Result<PrefabTable, String>
+{
+    for (code, prefab) in &mut table.prefabs
+    {
+        if let Some(ref name) = prefab.layer
+        {
+            TileLayer::from_name(name).ok_or(format!("Unknown tile layer '{}' in prefab '{}'", name, code))?;
+        }
+        for template in &mut prefab.entities
+        {
+            if let Some(ref sprite) = template.sprite
+            {
+                visual::layer_from_name(&sprite.layer).ok_or(format!("Unknown sprite layer '{}' in prefab '{}'", sprite.layer, code))?;
+            }
+            if let Some(ref collision) = template.collision
+            {
+                collision.check().map_err(|e| format!("{} in prefab '{}'", e, code))?;
+            }
+            if let Some(ref name) = template.animation
+            {
+                let clips = animations.animations.get(name).ok_or(format!("Unknown animation '{}' in prefab '{}'", name, code))?;
+                template.clips = Some(clips.clone());
+            }
+            if let Some(ref emitter) = template.emitter
+            {
+                let effect = effects.effects.get(&emitter.effect).ok_or(format!("Unknown particle effect '{}' in prefab '{}'", emitter.effect, code))?;
+                template.effect = Some(effect.clone());
+            }
+        }
+    }
+    Ok(table)
+}
+
+pub fn spawn_prefab(world: &mut World, level: &Level, code: &str, prefab: &Prefab, pos: Vector2<f32>)
+{
+    for template in &prefab.entities
+    {
+        let mut builder = world.create_now().with(Position(pos));
+
+        let mut region = vec2(0, 0);
+        let mut collision = Collision::passable();
+        if let Some(ref sprite) = template.sprite
+        {
+            region = vec2(sprite.region[0], sprite.region[1]);
+            let layer = visual::layer_from_name(&sprite.layer).expect(&format!("Unknown sprite layer '{}'", sprite.layer));
+            builder = builder.with(Sprite { region: region, layer: layer });
+        }
+        if let Some(ref motion) = template.motion
+        {
+            builder = builder.with(Motion::new(motion.speed));
+        }
+        if let Some(ref collision_data) = template.collision
+        {
+            collision = level.collision(code, collision_data.apply(collision));
+            builder = builder.with(collision);
+        }
+        if let Some(ref tracker) = template.tracker
+        {
+            builder = builder.with(PlayerTracker::new(tracker.delay, level.initial_stalker_path.clone()));
+        }
+        if let Some(ref gate) = template.gate
+        {
+            let open_region = vec2(gate.open_region[0], gate.open_region[1]);
+            builder = builder.with(ButtonGate { open: false, links: level.links_to(pos), closed_collision: collision, closed_region: region, open_region: open_region });
+        }
+        if let Some(ref clips) = template.clips
+        {
+            builder = builder.with(Animation::new(clips.clone()));
+        }
+        if let (Some(emitter), Some(effect)) = (&template.emitter, &template.effect)
+        {
+            builder = builder.with(Emitter::new(effect.clone(), emitter.on, pos));
+        }
+        if template.player
+        {
+            builder = builder.with(Player::default());
+        }
+        if template.hazard
+        {
+            builder = builder.with(Hazard);
+        }
+        if template.goal
+        {
+            builder = builder.with(Goal);
+        }
+        if template.button
+        {
+            builder = builder.with(Button(false));
+        }
+        if template.collectible
+        {
+            builder = builder.with(Collectible(false));
+        }
+
+        builder.build();
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    pub fn bad_names_are_errors()
+    {
+        let animations: AnimationTable = serde_yaml::from_str(include_str!("../assets/animations.yaml")).unwrap();
+        let effects: ParticleTable = serde_yaml::from_str(include_str!("../assets/particles.yaml")).unwrap();
+        let table = |yaml: &str| -> PrefabTable { serde_yaml::from_str(yaml).unwrap() };
+
+        let prefabs = resolve_prefabs(table(include_str!("../assets/entities.yaml")), &animations, &effects).unwrap();
+        assert!(prefabs.prefabs.values().flat_map(|prefab| &prefab.entities).any(|template| template.clips.is_some()));
+
+        let layer = "prefabs: { X: { name: thing, entities: [{ sprite: { region: [0, 0], layer: sky } }] } }";
+        assert_eq!(resolve_prefabs(table(layer), &animations, &effects).err().unwrap(), "Unknown sprite layer 'sky' in prefab 'X'");
+
+        let animation = "prefabs: { X: { name: thing, entities: [{ animation: dance }] } }";
+        assert_eq!(resolve_prefabs(table(animation), &animations, &effects).err().unwrap(), "Unknown animation 'dance' in prefab 'X'");
+
+        let effect = "prefabs: { X: { name: thing, entities: [{ emitter: { effect: smoke, on: move } }] } }";
+        assert_eq!(resolve_prefabs(table(effect), &animations, &effects).err().unwrap(), "Unknown particle effect 'smoke' in prefab 'X'");
+    }
+}
@@ -0,0 +1,180 @@
+use std::cmp::{max, min};
+use std::collections::{BTreeMap, BTreeSet};
+
+pub const MAX_STARS: u32 = 3;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LevelResult
+{
+    pub moves: u32,
+    pub collected: u32,
+    pub total_collectibles: u32,
+    pub stars: u32,
+    /// How long the run took, in milliseconds.
+    #[serde(default)]
+    pub time_ms: Option<u32>
+}
+
+impl LevelResult
+{
+    pub fn new(moves: u32, collected: u32, total_collectibles: u32, par: Option<u32>) -> Self
+    {
+        LevelResult
+        {
+            moves: moves,
+            collected: collected,
+            total_collectibles: total_collectibles,
+            stars: star_rating(moves, collected, total_collectibles, par),
+            time_ms: None
+        }
+    }
+
+    /// The best of each score from two results, which might not all come
+    /// from the same run.
+    fn best_of(&self, other: &LevelResult) -> LevelResult
+    {
+        let time_ms = match (self.time_ms, other.time_ms)
+        {
+            (Some(a), Some(b)) => Some(min(a, b)),
+            (a, b) => a.or(b)
+        };
+        LevelResult
+        {
+            moves: min(self.moves, other.moves),
+            collected: max(self.collected, other.collected),
+            total_collectibles: self.total_collectibles,
+            stars: max(self.stars, other.stars),
+            time_ms: time_ms
+        }
+    }
+}
+
+/// One star for finishing, one for picking up every collectible, and one for
+/// finishing within par. Levels without collectibles or without a par give
+/// those stars away for free.
+pub fn star_rating(moves: u32, collected: u32, total_collectibles: u32, par: Option<u32>) -> u32
+{
+    let mut stars = 1;
+    if collected >= total_collectibles
+    {
+        stars += 1;
+    }
+    let within_par = match par
+    {
+        Some(par) => moves <= par,
+        None => true
+    };
+    if within_par
+    {
+        stars += 1;
+    }
+    stars
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct Progress
+{
+    /// Best results keyed by pack and level name, as "pack/level".
+    pub levels: BTreeMap<String, LevelResult>,
+    #[serde(default)]
+    pub completed_packs: BTreeSet<String>,
+    /// Packs that have been unlocked, which stay unlocked even if their
+    /// unlock rules change.
+    #[serde(default)]
+    pub unlocked_packs: BTreeSet<String>
+}
+
+impl Progress
+{
+    /// Records a completed run, keeping the best of each score. Returns true
+    /// if any of them improved.
+    pub fn record(&mut self, level_name: &str, result: LevelResult) -> bool
+    {
+        let best = match self.levels.get(level_name)
+        {
+            Some(best) => best.best_of(&result),
+            None => result
+        };
+        let improved = self.levels.get(level_name) != Some(&best);
+        self.levels.insert(level_name.to_string(), best);
+        improved
+    }
+
+    pub fn best(&self, level_name: &str) -> Option<&LevelResult>
+    {
+        self.levels.get(level_name)
+    }
+
+    /// Whether the level after `previous_level` is open, which it is once
+    /// that's been finished. The first level, with none before it, always is.
+    pub fn level_unlocked(&self, previous_level: Option<&str>) -> bool
+    {
+        previous_level.is_none_or(|previous| self.best(previous).is_some())
+    }
+
+    pub fn total_stars(&self) -> u32
+    {
+        self.levels.values().map(|result| result.stars).sum()
+    }
+
+    pub fn complete_pack(&mut self, pack: &str)
+    {
+        self.completed_packs.insert(pack.to_string());
+    }
+
+    pub fn pack_complete(&self, pack: &str) -> bool
+    {
+        self.completed_packs.contains(pack)
+    }
+
+    pub fn unlock_pack(&mut self, pack: &str)
+    {
+        self.unlocked_packs.insert(pack.to_string());
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    pub fn star_ratings()
+    {
+        assert_eq!(star_rating(10, 0, 0, None), 3);
+        assert_eq!(star_rating(10, 1, 2, Some(10)), 2);
+        assert_eq!(star_rating(11, 2, 2, Some(10)), 2);
+        assert_eq!(star_rating(11, 1, 2, Some(10)), 1);
+    }
+
+    #[test]
+    pub fn keeps_best_result()
+    {
+        let mut progress = Progress::default();
+        assert!(progress.record("a", LevelResult::new(20, 1, 1, Some(10))));
+        assert!(!progress.record("a", LevelResult::new(30, 0, 1, Some(10))));
+        assert!(progress.record("a", LevelResult::new(9, 1, 1, Some(10))));
+        assert!(progress.record("a", LevelResult::new(8, 1, 1, Some(10))));
+        assert_eq!(progress.best("a").unwrap().moves, 8);
+        assert_eq!(progress.best("a").unwrap().stars, 3);
+
+        let slow = LevelResult { time_ms: Some(9000), .. LevelResult::new(40, 0, 1, Some(10)) };
+        assert!(progress.record("a", slow));
+        assert!(!progress.record("a", slow));
+        assert_eq!(progress.best("a").unwrap().moves, 8);
+        assert_eq!(progress.best("a").unwrap().time_ms, Some(9000));
+    }
+
+    #[test]
+    pub fn finishing_a_level_unlocks_the_next()
+    {
+        let mut progress = Progress::default();
+        assert!(progress.level_unlocked(None));
+        assert!(!progress.level_unlocked(Some("main/First")));
+
+        progress.record("main/First", LevelResult::new(12, 0, 0, Some(10)));
+        assert!(progress.level_unlocked(Some("main/First")));
+        assert!(!progress.level_unlocked(Some("main/Second")));
+    }
+}
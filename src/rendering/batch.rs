@@ -0,0 +1,92 @@
+use glium::{Surface, DrawParameters, Program};
+use glium::uniforms::Uniforms;
+use glium::vertex::VertexBuffer;
+
+use super::{Display, Mesh, quad_mesh};
+
+/// The smallest number of sprites the instance buffer has room for.
+const MIN_CAPACITY: usize = 256;
+
+#[derive(Copy, Clone)]
+struct Instance
+{
+    position: [f32; 2],
+    size: [f32; 2],
+    uv_offset: [f32; 2],
+    uv_scale: [f32; 2],
+    sprite_color: [f32; 4],
+    layer: f32
+}
+implement_vertex!(Instance, position, size, uv_offset, uv_scale, sprite_color, layer);
+
+/// Collects a frame's sprites and draws them all in one instanced call.
+/// The instance buffer is kept between frames and only grows, doubling
+/// whenever a frame has more sprites than it has room for.
+pub struct SpriteBatch
+{
+    display: Display,
+    mesh: Mesh,
+    instances: Vec<Instance>,
+    buffer: VertexBuffer<Instance>
+}
+
+impl SpriteBatch
+{
+    pub fn new(display: &Display) -> Self
+    {
+        SpriteBatch
+        {
+            display: display.clone(),
+            mesh: quad_mesh(display),
+            instances: Vec::with_capacity(MIN_CAPACITY),
+            buffer: VertexBuffer::empty_dynamic(display, MIN_CAPACITY).expect("Failed to build instance buffer")
+        }
+    }
+
+    pub fn clear(&mut self)
+    {
+        self.instances.clear();
+    }
+
+    /// Adds a sprite, centred on `position` and `size` across, showing the
+    /// part of the texture from `uv_offset` that's `uv_scale` across, with
+    /// its colours multiplied by `color`. Sprites are drawn in the order
+    /// they're pushed, so later ones go on top.
+    pub fn push(&mut self, position: [f32; 2], size: [f32; 2], uv_offset: [f32; 2], uv_scale: [f32; 2], color: [f32; 4], layer: u32)
+    {
+        self.instances.push(Instance { position: position, size: size, uv_offset: uv_offset, uv_scale: uv_scale, sprite_color: color, layer: layer as f32 });
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.instances.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.instances.is_empty()
+    }
+
+    /// Uploads the sprites and draws them with `shader`, which gets each
+    /// sprite's `position`, `size`, `uv_offset`, `uv_scale`, `sprite_color`
+    /// and `layer` as attributes.
+    pub fn draw<S, U>(&mut self, target: &mut S, shader: &Program, uniforms: &U, parameters: &DrawParameters)
+        where S: Surface, U: Uniforms
+    {
+        if self.instances.is_empty()
+        {
+            return;
+        }
+
+        if self.instances.len() > self.buffer.len()
+        {
+            let capacity = self.instances.len().next_power_of_two();
+            self.buffer = VertexBuffer::empty_dynamic(&self.display, capacity).expect("Failed to grow instance buffer");
+        }
+        let used = self.buffer.slice(0..self.instances.len()).unwrap();
+        used.write(&self.instances);
+
+        let instances = used.per_instance().expect("Instanced drawing isn't supported");
+        target.draw((&self.mesh.0, instances), &self.mesh.1, shader, uniforms, parameters).unwrap();
+    }
+}
@@ -0,0 +1,294 @@
+//! Pixel text for menus, titles and the HUD. The font is an image with
+//! every glyph in a grid of equal cells, described by `font.yaml`. Text is
+//! placed in virtual screen pixels, counting up from the bottom left, and
+//! drawn through the sprite batch like everything else.
+
+use glium::{Surface, DrawParameters, DepthTest, Depth, Blend};
+use glium::uniforms::{Sampler, MinifySamplerFilter, MagnifySamplerFilter, SamplerWrapFunction};
+use serde_yaml;
+
+use super::*;
+use assets::read_asset_string;
+
+pub const FONT_PATH: &str = "font.yaml";
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct FontMetrics
+{
+    pub image: String,
+    /// Each glyph's cell, in pixels, including the spacing after it.
+    pub cell: [u32; 2],
+    pub columns: u32,
+    /// The character in the first cell, and how many follow it.
+    pub first: u32,
+    pub count: u32,
+    pub line_height: u32
+}
+
+impl FontMetrics
+{
+    /// The cell showing `c`, or a question mark for characters the font
+    /// doesn't have.
+    pub fn glyph(&self, c: char) -> u32
+    {
+        let code = c as u32;
+        if code >= self.first && code < self.first + self.count
+        {
+            code - self.first
+        }
+        else
+        {
+            ('?' as u32).saturating_sub(self.first).min(self.count - 1)
+        }
+    }
+
+    pub fn advance(&self) -> u32
+    {
+        self.cell[0]
+    }
+
+    pub fn width(&self, line: &str) -> u32
+    {
+        line.chars().count() as u32 * self.advance()
+    }
+}
+
+pub fn load_font_metrics() -> Result<FontMetrics, String>
+{
+    let yaml = read_asset_string(FONT_PATH)?;
+    let metrics: FontMetrics = serde_yaml::from_str(&yaml).map_err(|e| format!("Failed to parse font: {}", e))?;
+    if metrics.cell[0] == 0 || metrics.cell[1] == 0 || metrics.columns == 0 || metrics.count == 0
+    {
+        return Err("The font needs cells, columns and glyphs".to_string());
+    }
+    Ok(metrics)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Align
+{
+    Left,
+    Centre,
+    Right
+}
+
+/// A string to draw. Its first line's top sits at `position`, with the
+/// lines starting there, centred on it or ending there by `align`, wrapped
+/// to `width` pixels if it's given.
+#[derive(Clone, Debug)]
+pub struct Text
+{
+    pub string: String,
+    pub position: Vector2<i32>,
+    pub align: Align,
+    pub width: Option<u32>
+}
+
+impl Text
+{
+    pub fn new<S: Into<String>>(string: S, position: Vector2<i32>, align: Align) -> Self
+    {
+        Text { string: string.into(), position: position, align: align, width: None }
+    }
+
+    pub fn wrapped(mut self, width: u32) -> Self
+    {
+        self.width = Some(width);
+        self
+    }
+}
+
+/// A glyph laid out: which cell, and where its bottom left corner goes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PlacedGlyph
+{
+    pub glyph: u32,
+    pub position: Vector2<i32>
+}
+
+/// Splits text into lines at newlines, and at spaces to keep lines inside
+/// `width` pixels. Words too long for a line of their own are broken.
+pub fn wrap(metrics: &FontMetrics, text: &str, width: Option<u32>) -> Vec<String>
+{
+    let limit = match width
+    {
+        Some(width) => ((width / metrics.advance()) as usize).max(1),
+        None => usize::MAX
+    };
+
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n')
+    {
+        let mut line = String::new();
+        for word in paragraph.split(' ')
+        {
+            let mut word: Vec<char> = word.chars().collect();
+            let line_length = line.chars().count();
+            if line_length > 0 && line_length + 1 + word.len() > limit
+            {
+                lines.push(line);
+                line = String::new();
+            }
+            else if line_length > 0
+            {
+                line.push(' ');
+            }
+
+            while word.len() > limit
+            {
+                let rest = word.split_off(limit);
+                lines.push(word.into_iter().collect());
+                word = rest;
+            }
+            line.extend(word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Places each glyph of `text`, top line first.
+pub fn layout(metrics: &FontMetrics, text: &Text) -> Vec<PlacedGlyph>
+{
+    let (x, top) = (text.position.components[0], text.position.components[1]);
+    let mut glyphs = Vec::new();
+    for (index, line) in wrap(metrics, &text.string, text.width).iter().enumerate()
+    {
+        let width = metrics.width(line) as i32;
+        let left = match text.align
+        {
+            Align::Left => x,
+            Align::Centre => x - width / 2,
+            Align::Right => x - width
+        };
+        let bottom = top - metrics.cell[1] as i32 - (index as u32 * metrics.line_height) as i32;
+        for (column, c) in line.chars().enumerate()
+        {
+            if c == ' '
+            {
+                continue;
+            }
+            glyphs.push(PlacedGlyph { glyph: metrics.glyph(c), position: vec2(left + (column as u32 * metrics.advance()) as i32, bottom) });
+        }
+    }
+    glyphs
+}
+
+/// Draws text from the font image with the sprite shader.
+pub struct TextRenderer
+{
+    pub shader: Shader,
+    pub batch: SpriteBatch,
+    pub texture: Texture,
+    pub metrics: FontMetrics,
+    /// The font image's size, in pixels.
+    size: (u32, u32)
+}
+
+impl TextRenderer
+{
+    pub fn new(display: &Display) -> Self
+    {
+        let metrics = load_font_metrics().unwrap_or_else(|e| panic!("{}", e));
+        let (texture, size) = load_texture(display, &get_asset_bytes(&metrics.image));
+        TextRenderer
+        {
+            shader: load_shader(display, get_asset_string("shaders/sprite.vs"), get_asset_string("shaders/sprite.fs")),
+            batch: SpriteBatch::new(display),
+            texture: texture,
+            metrics: metrics,
+            size: size
+        }
+    }
+
+    /// Loads the shader, font and its image again, keeping the old ones if
+    /// any fails.
+    pub fn reload(&mut self, display: &Display) -> Result<(), String>
+    {
+        let shader = try_load_shader_asset(display, "shaders/sprite")?;
+        let metrics = load_font_metrics()?;
+        let (texture, size) = try_load_texture(display, &read_asset_bytes(&metrics.image)?)?;
+        self.shader = shader;
+        self.metrics = metrics;
+        self.texture = texture;
+        self.size = size;
+        Ok(())
+    }
+
+    /// Draws each text on a screen of `resolution` pixels, on `layer` and
+    /// coloured by `tint`.
+    pub fn draw<S>(&mut self, target: &mut S, texts: &[Text], resolution: (u32, u32), layer: u32, tint: [f32; 4])
+        where S: Surface
+    {
+        let colormap = Sampler::new(&self.texture)
+            .minify_filter(MinifySamplerFilter::Nearest)
+            .magnify_filter(MagnifySamplerFilter::Nearest)
+            .wrap_function(SamplerWrapFunction::Clamp);
+
+        let (width, height) = (self.size.0 as f32, self.size.1 as f32);
+        let (cell_width, cell_height) = (self.metrics.cell[0] as f32, self.metrics.cell[1] as f32);
+        let uv_scale = [cell_width / width, cell_height / height];
+
+        self.batch.clear();
+        for text in texts
+        {
+            for placed in layout(&self.metrics, text)
+            {
+                // Rows of cells count down from the top of the image, and
+                // texture coordinates up from the bottom.
+                let (column, row) = (placed.glyph % self.metrics.columns, placed.glyph / self.metrics.columns);
+                let uv_offset = [column as f32 * cell_width / width, (height - (row + 1) as f32 * cell_height) / height];
+                let centre = [placed.position.components[0] as f32 + cell_width * 0.5, placed.position.components[1] as f32 + cell_height * 0.5];
+                self.batch.push(centre, [cell_width, cell_height], uv_offset, uv_scale, [1.0; 4], layer);
+            }
+        }
+
+        let (screen_width, screen_height) = (resolution.0 as f32, resolution.1 as f32);
+        self.batch.draw(
+            target,
+            &self.shader,
+            &uniform!
+            {
+                projection: [2.0 / screen_width, 2.0 / screen_height],
+                camera_pos: [screen_width * 0.5, screen_height * 0.5],
+                colormap: colormap,
+                tint: tint
+            },
+            &DrawParameters
+            {
+                depth: Depth
+                {
+                    test: DepthTest::IfLess,
+                    write: false,
+                    .. Default::default()
+                },
+                blend: Blend::alpha_blending(),
+                .. Default::default()
+            });
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    pub fn wraps_and_aligns()
+    {
+        let metrics: FontMetrics = serde_yaml::from_str(include_str!("../../assets/font.yaml")).unwrap();
+        assert_eq!(metrics.glyph('A'), 33);
+        assert_eq!(metrics.glyph('é'), metrics.glyph('?'));
+
+        assert_eq!(wrap(&metrics, "a small world\nend", Some(28)), vec!["a small", "world", "end"]);
+        assert_eq!(wrap(&metrics, "abcdefghij", Some(16)), vec!["abcd", "efgh", "ij"]);
+
+        let glyphs = layout(&metrics, &Text::new("ab c\nd", vec2(100, 50), Align::Right));
+        let positions: Vec<Vector2<i32>> = glyphs.iter().map(|glyph| glyph.position).collect();
+        assert_eq!(positions, vec![vec2(84, 44), vec2(88, 44), vec2(96, 44), vec2(96, 37)]);
+
+        let centred = layout(&metrics, &Text::new("abc", vec2(100, 50), Align::Centre));
+        assert_eq!(centred[0].position, vec2(94, 44));
+    }
+}
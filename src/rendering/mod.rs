@@ -1,4 +1,5 @@
-use glium::{Program, Texture2d};
+use glium::{Program, Texture2d, Surface, DrawParameters, DepthTest, Depth, Blend};
+use glium::uniforms::{Sampler, MinifySamplerFilter, MagnifySamplerFilter, SamplerWrapFunction};
 use glium::backend::glutin_backend::GlutinFacade;
 use glium::index::{IndexBufferAny, IndexBuffer, PrimitiveType};
 use glium::program::{ProgramCreationInput};
@@ -6,11 +7,29 @@ use glium::texture::{RawImage2d, MipmapsOption};
 use glium::vertex::{VertexBufferAny, VertexBuffer};
 use image::{load_from_memory_with_format, ImageFormat};
 
+use assets::{get_asset_string, get_asset_bytes, read_asset_string, read_asset_bytes};
+
+use vectors::*;
+
+pub mod batch;
+pub mod font;
+pub mod screen;
+pub mod software;
+pub mod transition;
+
+pub use self::batch::SpriteBatch;
+pub use self::font::{Align, Text, TextRenderer};
+pub use self::screen::{Canvas, VirtualScreen};
+pub use self::transition::{Transition, TransitionKind};
+
 pub type Display = GlutinFacade;
 pub type Shader = Program;
 pub type Texture = Texture2d;
 pub type Mesh = (VertexBufferAny, IndexBufferAny);
 
+/// How many pixels across each region of the atlas is.
+pub const ATLAS_TILE_SIZE: u32 = 16;
+
 pub struct TextureAtlas
 {
     pub texture: Texture,
@@ -30,6 +49,26 @@ impl TextureAtlas
     }
 }
 
+/// One sprite to draw: which atlas region, where, and on which layer.
+#[derive(Copy, Clone)]
+pub struct SpriteInstance
+{
+    pub layer: u32,
+    pub position: Vector2<f32>,
+    pub region: Vector2<u32>
+}
+
+/// A particle to draw: a sprite that can be any size and colour.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ParticleSprite
+{
+    pub position: Vector2<f32>,
+    /// How many tiles across it is.
+    pub size: f32,
+    pub region: Vector2<u32>,
+    pub color: [f32; 4]
+}
+
 #[derive(Copy, Clone)]
 struct Vertex
 {
@@ -41,6 +80,12 @@ implement_vertex!(Vertex, offset, uv);
 
 pub fn load_shader<V, F>(display: &Display, vertex_source: V, fragment_source: F) -> Shader
     where V: AsRef<str>, F: AsRef<str>
+{
+    try_load_shader(display, vertex_source, fragment_source).unwrap_or_else(|e| panic!("Failed to load shaders! {}", e))
+}
+
+pub fn try_load_shader<V, F>(display: &Display, vertex_source: V, fragment_source: F) -> Result<Shader, String>
+    where V: AsRef<str>, F: AsRef<str>
 {
     Shader::new(display, ProgramCreationInput::SourceCode
     {
@@ -52,7 +97,16 @@ pub fn load_shader<V, F>(display: &Display, vertex_source: V, fragment_source: F
         tessellation_evaluation_shader: None,
         transform_feedback_varyings: None,
         uses_point_size: false
-    }).expect("Failed to load shaders!")
+    }).map_err(|e| e.to_string())
+}
+
+/// Loads a shader from a pair of asset files, named without the .vs and .fs
+/// extensions.
+pub fn try_load_shader_asset(display: &Display, name: &str) -> Result<Shader, String>
+{
+    let vertex_source = read_asset_string(format!("{}.vs", name))?;
+    let fragment_source = read_asset_string(format!("{}.fs", name))?;
+    try_load_shader(display, vertex_source, fragment_source).map_err(|e| format!("Failed to compile '{}': {}", name, e))
 }
 
 pub fn quad_mesh(display: &Display) -> Mesh
@@ -71,19 +125,37 @@ pub fn quad_mesh(display: &Display) -> Mesh
 
 pub fn load_texture(display: &Display, bytes: &[u8]) -> (Texture, (u32, u32))
 {
-    let image = load_from_memory_with_format(bytes, ImageFormat::PNG).expect("Failed to decode image").to_rgba();
+    try_load_texture(display, bytes).unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub fn try_load_texture(display: &Display, bytes: &[u8]) -> Result<(Texture, (u32, u32)), String>
+{
+    let image = load_from_memory_with_format(bytes, ImageFormat::PNG).map_err(|e| format!("Failed to decode image: {}", e))?.to_rgba();
     let image_dimensions = image.dimensions();
     let image = RawImage2d::from_raw_rgba_reversed(image.into_raw(), image_dimensions);
-    let texture = Texture2d::with_mipmaps(display, image, MipmapsOption::NoMipmap).expect("Failed to load texture");
-    (texture, image_dimensions)
+    let texture = Texture2d::with_mipmaps(display, image, MipmapsOption::NoMipmap).map_err(|e| format!("Failed to load texture: {:?}", e))?;
+    Ok((texture, image_dimensions))
 }
 
 pub fn load_texture_atlas(display: &Display, bytes: &[u8], tile_size: u32) -> TextureAtlas
 {
-    let (texture, dimensions) = load_texture(display, bytes);
+    try_load_texture_atlas(display, bytes, tile_size).unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub fn try_load_texture_atlas(display: &Display, bytes: &[u8], tile_size: u32) -> Result<TextureAtlas, String>
+{
+    let (texture, dimensions) = try_load_texture(display, bytes)?;
     let (w, h) = dimensions;
     let (tw, th) = (w / tile_size, h / tile_size);
-    TextureAtlas { texture: texture, width_tiles: tw, height_tiles: th }
+    Ok(TextureAtlas { texture: texture, width_tiles: tw, height_tiles: th })
+}
+
+pub fn set_window_title(display: &Display, title: &str)
+{
+    if let Some(window) = display.get_window()
+    {
+        window.set_title(title);
+    }
 }
 
 pub fn calculate_projection(resolution: (u32, u32), tile_size: u32) -> [f32; 2]
@@ -91,6 +163,100 @@ pub fn calculate_projection(resolution: (u32, u32), tile_size: u32) -> [f32; 2]
     let (w, h) = resolution;
     let (w, h) = (w as f32, h as f32);
     let tile_size = tile_size as f32;
-    let projection = [2.0 * tile_size / w, 2.0 * tile_size / h];
-    projection
+    [2.0 * tile_size / w, 2.0 * tile_size / h]
+}
+
+/// Draws sprites from the texture atlas with the sprite shader, all of a
+/// frame's at once.
+pub struct SpriteRenderer
+{
+    pub shader: Shader,
+    pub batch: SpriteBatch,
+    pub atlas: TextureAtlas
+}
+
+impl SpriteRenderer
+{
+    pub fn new(display: &Display) -> Self
+    {
+        SpriteRenderer
+        {
+            shader: load_shader(display, get_asset_string("shaders/sprite.vs"), get_asset_string("shaders/sprite.fs")),
+            batch: SpriteBatch::new(display),
+            atlas: load_texture_atlas(display, &get_asset_bytes("atlas.png"), ATLAS_TILE_SIZE)
+        }
+    }
+
+    /// Loads the shader and atlas again, keeping the old ones if either
+    /// fails.
+    pub fn reload(&mut self, display: &Display) -> Result<(), String>
+    {
+        let shader = try_load_shader_asset(display, "shaders/sprite")?;
+        let atlas = try_load_texture_atlas(display, &read_asset_bytes("atlas.png")?, ATLAS_TILE_SIZE)?;
+        self.shader = shader;
+        self.atlas = atlas;
+        Ok(())
+    }
+
+    /// Draws sprites back to front by layer, snapped to whole pixels, with
+    /// their colours multiplied by `tint`.
+    pub fn draw<S>(&mut self, target: &mut S, mut sprites: Vec<SpriteInstance>, projection: [f32; 2], camera_pos: Vector2<f32>, tile_size: u32, tint: [f32; 4])
+        where S: Surface
+    {
+        sprites.sort_by_key(|sprite| sprite.layer);
+
+        self.batch.clear();
+        for sprite in sprites
+        {
+            let (uv_offset, uv_scale) = self.atlas.get_uv_offset_scale(sprite.region.components[0], sprite.region.components[1]);
+            let pixel_position = (sprite.position * tile_size as f32).round_i32();
+            let rounded_position = vec2(pixel_position.components[0] as f32, pixel_position.components[1] as f32) * (1.0 / tile_size as f32);
+            self.batch.push(rounded_position.components, [1.0, 1.0], uv_offset, uv_scale, [1.0; 4], sprite.layer);
+        }
+        self.draw_batch(target, projection, camera_pos, tint);
+    }
+
+    /// Draws particles over everything, in the order given.
+    pub fn draw_particles<S>(&mut self, target: &mut S, particles: &[ParticleSprite], projection: [f32; 2], camera_pos: Vector2<f32>, layer: u32, tint: [f32; 4])
+        where S: Surface
+    {
+        self.batch.clear();
+        for particle in particles
+        {
+            let (uv_offset, uv_scale) = self.atlas.get_uv_offset_scale(particle.region.components[0], particle.region.components[1]);
+            self.batch.push(particle.position.components, [particle.size, particle.size], uv_offset, uv_scale, particle.color, layer);
+        }
+        self.draw_batch(target, projection, camera_pos, tint);
+    }
+
+    fn draw_batch<S>(&mut self, target: &mut S, projection: [f32; 2], camera_pos: Vector2<f32>, tint: [f32; 4])
+        where S: Surface
+    {
+        let colormap = Sampler::new(&self.atlas.texture)
+            .minify_filter(MinifySamplerFilter::Nearest)
+            .magnify_filter(MagnifySamplerFilter::Nearest)
+            .wrap_function(SamplerWrapFunction::Clamp);
+
+        self.batch.draw(
+            target,
+            &self.shader,
+            &uniform!
+            {
+                projection: projection,
+                camera_pos: camera_pos.components,
+                colormap: colormap,
+                tint: tint
+            },
+            &DrawParameters
+            {
+                depth: Depth
+                {
+                    test: DepthTest::IfLess,
+                    write: false,
+                    .. Default::default()
+                },
+                blend: Blend::alpha_blending(),
+                .. Default::default()
+            });
+    }
 }
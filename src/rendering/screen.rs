@@ -0,0 +1,120 @@
+use glium::{Frame, Rect, Surface, BlitTarget};
+use glium::framebuffer::{SimpleFrameBuffer, DepthRenderBuffer};
+use glium::texture::{DepthFormat, MipmapsOption, UncompressedFloatFormat};
+use glium::uniforms::MagnifySamplerFilter;
+
+use super::{Display, Texture};
+use super::transition::{Transition, TransitionRenderer};
+
+/// What states draw on: the virtual screen, not the window.
+pub type Canvas<'a> = SimpleFrameBuffer<'a>;
+
+/// The game's screen, drawn at its own small resolution and then scaled up
+/// to fill as much of the window as it can without stretching, with black
+/// bars round the rest. The last frame of the state before is kept, for
+/// transitions from it.
+pub struct VirtualScreen
+{
+    pub resolution: (u32, u32),
+    color: Texture,
+    previous: Texture,
+    depth: DepthRenderBuffer,
+    transitions: TransitionRenderer
+}
+
+impl VirtualScreen
+{
+    pub fn new(display: &Display, resolution: (u32, u32)) -> Self
+    {
+        let (width, height) = resolution;
+        let texture = || Texture::empty_with_format(display, UncompressedFloatFormat::U8U8U8U8, MipmapsOption::NoMipmap, width, height)
+            .expect("Failed to build screen texture");
+        let screen = VirtualScreen
+        {
+            resolution: resolution,
+            color: texture(),
+            previous: texture(),
+            depth: DepthRenderBuffer::new(display, DepthFormat::I24, width, height).expect("Failed to build screen depth buffer"),
+            transitions: TransitionRenderer::new(display)
+        };
+        // The first state comes in from black.
+        screen.previous.as_surface().clear_color(0.0, 0.0, 0.0, 1.0);
+        screen
+    }
+
+    pub fn reload(&mut self, display: &Display) -> Result<(), String>
+    {
+        self.transitions.reload(display)
+    }
+
+    /// Keeps what's on the screen now, for the next transition to start
+    /// from.
+    pub fn keep_frame(&self)
+    {
+        self.color.as_surface().fill(&self.previous.as_surface(), MagnifySamplerFilter::Nearest);
+    }
+
+    pub fn canvas(&self, display: &Display) -> Canvas<'_>
+    {
+        SimpleFrameBuffer::with_depth_buffer(display, &self.color, &self.depth).expect("Failed to build screen framebuffer")
+    }
+
+    /// Copies the screen into the window, letterboxed, blended with the
+    /// kept frame if there's a transition going.
+    pub fn present(&self, frame: &mut Frame, transition: Option<&Transition>)
+    {
+        frame.clear_color(0.0, 0.0, 0.0, 1.0);
+        let rect = letterbox(self.resolution, frame.get_dimensions());
+        match transition
+        {
+            Some(transition) if !transition.finished() => self.transitions.draw(frame, rect, &self.previous, &self.color, transition),
+            _ =>
+            {
+                let target = BlitTarget { left: rect.left, bottom: rect.bottom, width: rect.width as i32, height: rect.height as i32 };
+                self.color.as_surface().blit_whole_color_to(frame, &target, MagnifySamplerFilter::Nearest);
+            }
+        }
+    }
+}
+
+/// Where a screen of `resolution` goes in a window of `window` pixels: as
+/// many whole times bigger as fits, in the middle. Windows smaller than the
+/// screen get it shrunk to fit instead.
+pub fn letterbox(resolution: (u32, u32), window: (u32, u32)) -> Rect
+{
+    let (width, height) = resolution;
+    let (window_width, window_height) = window;
+    let scale = (window_width / width).min(window_height / height);
+    let (scaled_width, scaled_height) = match scale
+    {
+        0 =>
+        {
+            let fit = (window_width as f32 / width as f32).min(window_height as f32 / height as f32);
+            (((width as f32 * fit) as u32).max(1), ((height as f32 * fit) as u32).max(1))
+        },
+        _ => (width * scale, height * scale)
+    };
+    Rect
+    {
+        left: (window_width.saturating_sub(scaled_width)) / 2,
+        bottom: (window_height.saturating_sub(scaled_height)) / 2,
+        width: scaled_width,
+        height: scaled_height
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    pub fn scales_by_whole_numbers()
+    {
+        assert_eq!(letterbox((256, 144), (1024, 576)), Rect { left: 0, bottom: 0, width: 1024, height: 576 });
+        assert_eq!(letterbox((256, 144), (1920, 1080)), Rect { left: 64, bottom: 36, width: 1792, height: 1008 });
+        assert_eq!(letterbox((256, 144), (800, 800)), Rect { left: 16, bottom: 184, width: 768, height: 432 });
+        assert_eq!(letterbox((256, 144), (128, 144)), Rect { left: 0, bottom: 36, width: 128, height: 72 });
+    }
+}
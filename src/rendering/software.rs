@@ -0,0 +1,117 @@
+//! Draws sprites on the CPU the way the sprite shader does, for rendering
+//! without a GPU: golden-image tests and level thumbnails. Sprites sample
+//! the atlas with nearest filtering and blend over each other in the same
+//! order, so a frame comes out the same as on screen.
+
+use std::path::Path;
+use image::{self, load_from_memory_with_format, ImageFormat, RgbaImage};
+
+use super::{SpriteInstance, ATLAS_TILE_SIZE};
+use assets::read_asset_bytes;
+use vectors::*;
+
+/// The atlas as an image, the right way up.
+pub struct SoftwareAtlas
+{
+    pub image: RgbaImage,
+    pub tile_size: u32
+}
+
+pub fn try_load_software_atlas() -> Result<SoftwareAtlas, String>
+{
+    SoftwareAtlas::from_png(&read_asset_bytes("atlas.png")?)
+}
+
+impl SoftwareAtlas
+{
+    pub fn from_png(bytes: &[u8]) -> Result<Self, String>
+    {
+        let image = load_from_memory_with_format(bytes, ImageFormat::PNG).map_err(|e| format!("Failed to decode atlas: {}", e))?.to_rgba();
+        Ok(SoftwareAtlas { image: image, tile_size: ATLAS_TILE_SIZE })
+    }
+
+    /// The texel at (x, y) in the region, counting up from its bottom left
+    /// like the shader's texture coordinates.
+    fn texel(&self, region: Vector2<u32>, x: u32, y: u32) -> [f32; 4]
+    {
+        let height = self.image.height();
+        let column = region.components[0] * self.tile_size + x;
+        let row_from_bottom = region.components[1] * self.tile_size + y;
+        if column >= self.image.width() || row_from_bottom >= height
+        {
+            return [0.0; 4];
+        }
+        let texel = self.image.get_pixel(column, height - 1 - row_from_bottom).data;
+        [texel[0] as f32 / 255.0, texel[1] as f32 / 255.0, texel[2] as f32 / 255.0, texel[3] as f32 / 255.0]
+    }
+}
+
+/// Draws a frame of `resolution` pixels, cleared to `background`, with the
+/// sprites centred on `camera_pos` and `tile_size` pixels to a tile.
+pub fn render_sprites(atlas: &SoftwareAtlas, mut sprites: Vec<SpriteInstance>, resolution: (u32, u32), camera_pos: Vector2<f32>,
+    tile_size: u32, background: (f32, f32, f32, f32), tint: [f32; 4]) -> RgbaImage
+{
+    let (width, height) = resolution;
+    let clear = [background.0, background.1, background.2, background.3];
+    let mut frame = vec![clear; (width * height) as usize];
+
+    sprites.sort_by_key(|sprite| sprite.layer);
+
+    let tile = tile_size as f32;
+    for sprite in sprites
+    {
+        // Snapped to whole pixels, as SpriteRenderer does.
+        let pixel_position = (sprite.position * tile).round_i32();
+        let position = vec2(pixel_position.components[0] as f32, pixel_position.components[1] as f32) * (1.0 / tile);
+
+        // The quad's bottom left corner, in pixels up from the bottom left
+        // of the frame.
+        let left = (position.components[0] - 0.5 - camera_pos.components[0]) * tile + width as f32 / 2.0;
+        let bottom = (position.components[1] - 0.5 - camera_pos.components[1]) * tile + height as f32 / 2.0;
+
+        let columns = (left.floor().max(0.0) as u32)..((left + tile).ceil().min(width as f32).max(0.0) as u32);
+        for x in columns
+        {
+            let u = (x as f32 + 0.5 - left) / tile;
+            if !(0.0..1.0).contains(&u)
+            {
+                continue;
+            }
+            let rows = (bottom.floor().max(0.0) as u32)..((bottom + tile).ceil().min(height as f32).max(0.0) as u32);
+            for y in rows
+            {
+                let v = (y as f32 + 0.5 - bottom) / tile;
+                if !(0.0..1.0).contains(&v)
+                {
+                    continue;
+                }
+                let texel = atlas.texel(sprite.region, (u * atlas.tile_size as f32) as u32, (v * atlas.tile_size as f32) as u32);
+                let source = [texel[0] * tint[0], texel[1] * tint[1], texel[2] * tint[2], texel[3] * tint[3]];
+                let destination = &mut frame[((height - 1 - y) * width + x) as usize];
+                let alpha = source[3];
+                for channel in 0..4
+                {
+                    destination[channel] = source[channel] * alpha + destination[channel] * (1.0 - alpha);
+                }
+            }
+        }
+    }
+
+    let bytes = frame.iter().flat_map(|pixel| pixel.iter().map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8)).collect();
+    RgbaImage::from_raw(width, height, bytes).expect("Frame is the wrong size")
+}
+
+/// Makes an image `scale` times bigger, keeping its pixels sharp.
+pub fn upscale(frame: &RgbaImage, scale: u32) -> RgbaImage
+{
+    match scale
+    {
+        0 | 1 => frame.clone(),
+        _ => image::imageops::resize(frame, frame.width() * scale, frame.height() * scale, image::FilterType::Nearest)
+    }
+}
+
+pub fn write_png(frame: &RgbaImage, path: &Path) -> Result<(), String>
+{
+    frame.save(path).map_err(|e| format!("Could not write '{}': {}", path.display(), e))
+}
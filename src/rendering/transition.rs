@@ -0,0 +1,165 @@
+//! Blends between the last frame of one state and the first frames of the
+//! next, instead of cutting straight from one to the other.
+
+use glium::{Surface, Rect, DrawParameters};
+use glium::uniforms::{Sampler, MinifySamplerFilter, MagnifySamplerFilter, SamplerWrapFunction};
+
+use super::*;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TransitionKind
+{
+    /// Cross-fades, for moving between menus.
+    Fade,
+    /// Sweeps the new screen in from the left, for restarting.
+    Wipe,
+    /// Closes a circle on the player, then opens one on the new screen,
+    /// for finishing a level.
+    Iris,
+    /// Swaps pixels over at random, for getting caught.
+    Dissolve
+}
+
+impl TransitionKind
+{
+    /// How long it takes, in seconds.
+    pub fn duration(&self) -> f32
+    {
+        match *self
+        {
+            TransitionKind::Fade => 0.25,
+            TransitionKind::Wipe => 0.3,
+            TransitionKind::Iris => 0.8,
+            TransitionKind::Dissolve => 0.6
+        }
+    }
+
+    /// What the transition shader calls it.
+    fn shader_kind(&self) -> i32
+    {
+        match *self
+        {
+            TransitionKind::Fade => 0,
+            TransitionKind::Wipe => 1,
+            TransitionKind::Iris => 2,
+            TransitionKind::Dissolve => 3
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transition
+{
+    pub kind: TransitionKind,
+    pub elapsed: f32,
+    /// Where an iris closes, from 0 to 1 across the screen each way.
+    pub focus: [f32; 2]
+}
+
+impl Transition
+{
+    pub fn new(kind: TransitionKind) -> Self
+    {
+        Transition { kind: kind, elapsed: 0.0, focus: [0.5, 0.5] }
+    }
+
+    pub fn iris(focus: [f32; 2]) -> Self
+    {
+        Transition { focus: focus, .. Transition::new(TransitionKind::Iris) }
+    }
+
+    pub fn update(&mut self, dt: f64)
+    {
+        self.elapsed += dt as f32;
+    }
+
+    /// How far through it is, from 0 to 1.
+    pub fn progress(&self) -> f32
+    {
+        (self.elapsed / self.kind.duration()).clamp(0.0, 1.0)
+    }
+
+    pub fn finished(&self) -> bool
+    {
+        self.elapsed >= self.kind.duration()
+    }
+}
+
+/// Draws transitions with the transition shader.
+pub struct TransitionRenderer
+{
+    shader: Shader,
+    mesh: Mesh
+}
+
+impl TransitionRenderer
+{
+    pub fn new(display: &Display) -> Self
+    {
+        TransitionRenderer
+        {
+            shader: load_shader(display, get_asset_string("shaders/transition.vs"), get_asset_string("shaders/transition.fs")),
+            mesh: quad_mesh(display)
+        }
+    }
+
+    /// Loads the shader again, keeping the old one if it fails.
+    pub fn reload(&mut self, display: &Display) -> Result<(), String>
+    {
+        self.shader = try_load_shader_asset(display, "shaders/transition")?;
+        Ok(())
+    }
+
+    /// Draws the blend of `outgoing` and `incoming` into `rect` of `target`.
+    pub fn draw<S>(&self, target: &mut S, rect: Rect, outgoing: &Texture, incoming: &Texture, transition: &Transition)
+        where S: Surface
+    {
+        target.draw(
+            &self.mesh.0,
+            &self.mesh.1,
+            &self.shader,
+            &uniform!
+            {
+                outgoing: nearest(outgoing),
+                incoming: nearest(incoming),
+                kind: transition.kind.shader_kind(),
+                progress: transition.progress(),
+                focus: transition.focus,
+                resolution: [incoming.get_width() as f32, incoming.get_height().unwrap_or(1) as f32]
+            },
+            &DrawParameters
+            {
+                viewport: Some(rect),
+                .. Default::default()
+            }).unwrap();
+    }
+}
+
+fn nearest(texture: &Texture) -> Sampler<'_, Texture>
+{
+    Sampler::new(texture)
+        .minify_filter(MinifySamplerFilter::Nearest)
+        .magnify_filter(MagnifySamplerFilter::Nearest)
+        .wrap_function(SamplerWrapFunction::Clamp)
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    pub fn runs_for_its_duration()
+    {
+        let mut transition = Transition::iris([0.25, 0.5]);
+        transition.update(0.4);
+        assert_eq!(transition.progress(), 0.5);
+        assert!(!transition.finished());
+
+        transition.update(1.0);
+        assert_eq!(transition.progress(), 1.0);
+        assert!(transition.finished());
+        assert_eq!(transition.focus, [0.25, 0.5]);
+    }
+}
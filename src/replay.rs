@@ -0,0 +1,89 @@
+//! Replays: the moves made on a level, which can be played back exactly.
+//! Each move is a letter, L, R, U or D.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde_yaml;
+
+use save;
+use vectors::*;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Replay
+{
+    /// The id of the pack the level is in.
+    pub pack: String,
+    /// The level's name.
+    pub level: String,
+    pub moves: String
+}
+
+impl Replay
+{
+    pub fn new(pack: &str, level: &str, dirs: &[Vector2<f32>]) -> Self
+    {
+        Replay { pack: pack.to_string(), level: level.to_string(), moves: dirs.iter().map(|dir| move_letter(*dir)).collect() }
+    }
+
+    pub fn dirs(&self) -> Result<Vec<Vector2<f32>>, String>
+    {
+        self.moves.chars().filter(|c| !c.is_whitespace()).map(|c| match c.to_ascii_uppercase()
+        {
+            'L' => Ok(vec2(-1.0, 0.0)),
+            'R' => Ok(vec2(1.0, 0.0)),
+            'U' => Ok(vec2(0.0, 1.0)),
+            'D' => Ok(vec2(0.0, -1.0)),
+            _ => Err(format!("'{}' isn't a move", c))
+        }).collect()
+    }
+}
+
+pub fn move_letter(dir: Vector2<f32>) -> char
+{
+    match (dir.components[0] as i32, dir.components[1] as i32)
+    {
+        (-1, 0) => 'L',
+        (1, 0) => 'R',
+        (0, 1) => 'U',
+        (0, -1) => 'D',
+        _ => panic!("{:?} isn't a move", dir)
+    }
+}
+
+pub fn load_replay(path: &Path) -> Result<Replay, String>
+{
+    let yaml = fs::read_to_string(path).map_err(|e| format!("Could not read replay '{}': {}", path.display(), e))?;
+    let replay: Replay = serde_yaml::from_str(&yaml).map_err(|e| format!("Failed to parse replay '{}': {}", path.display(), e))?;
+    replay.dirs()?;
+    Ok(replay)
+}
+
+/// Where the replay of level `number` (from 1) in `pack` goes when
+/// recording to `base`: beside it, named after it, the pack and the level,
+/// so that each level finished keeps its own.
+pub fn record_path(base: &Path, pack: &str, number: usize) -> PathBuf
+{
+    let stem = base.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let extension = base.extension().map(|extension| extension.to_string_lossy().into_owned()).unwrap_or("yaml".to_string());
+    base.with_file_name(format!("{}-{}-{}.{}", stem, pack, number, extension))
+}
+
+pub fn write_replay(replay: &Replay, path: &Path) -> Result<(), String>
+{
+    let yaml = serde_yaml::to_string(replay).map_err(|e| e.to_string())?;
+    save::write_atomically(path, &yaml, false).map_err(|e| format!("Could not write replay '{}': {}", path.display(), e))
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    pub fn records_each_level_to_its_own_file()
+    {
+        assert_eq!(record_path(Path::new("runs/moves.yaml"), "main", 3), PathBuf::from("runs/moves-main-3.yaml"));
+        assert_eq!(record_path(Path::new("moves"), "extra", 12), PathBuf::from("moves-extra-12.yaml"));
+    }
+}
@@ -0,0 +1,210 @@
+//! The save file, which keeps the player's progress between runs. It lives in
+//! the user's config directory and is replaced atomically, keeping the
+//! previous save as a backup to fall back on if the new one is ever
+//! unreadable.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use serde_yaml;
+
+use progress::Progress;
+
+/// Bumped whenever the format changes in a way older versions can't read.
+pub const SAVE_VERSION: u32 = 1;
+pub const SAVE_NAME: &str = "save.yaml";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SaveData
+{
+    pub version: u32,
+    pub progress: Progress,
+    /// The pack that was being played, by id.
+    #[serde(default)]
+    pub last_pack: Option<String>,
+    /// The level each pack was left on.
+    #[serde(default)]
+    pub last_levels: BTreeMap<String, usize>
+}
+
+impl Default for SaveData
+{
+    fn default() -> Self
+    {
+        SaveData { version: SAVE_VERSION, progress: Progress::default(), last_pack: None, last_levels: BTreeMap::new() }
+    }
+}
+
+pub fn default_save_path() -> Option<PathBuf>
+{
+    config_path(SAVE_NAME)
+}
+
+/// Where the game's file called `name` goes in this platform's config
+/// directory, if there's a home or config directory to put it in.
+pub fn config_path(name: &str) -> Option<PathBuf>
+{
+    let var = |name: &str| env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+    let config_dir = if cfg!(target_os = "windows")
+    {
+        var("APPDATA")
+    }
+    else if cfg!(target_os = "macos")
+    {
+        var("HOME").map(|home| home.join("Library").join("Application Support"))
+    }
+    else
+    {
+        var("XDG_CONFIG_HOME").or_else(|| var("HOME").map(|home| home.join(".config")))
+    };
+    config_dir.map(|dir| dir.join("smallworld").join(name))
+}
+
+/// Reads the save at `path`, falling back to its backup if it's missing or
+/// broken. A broken save is moved aside rather than overwritten, in case
+/// it's worth recovering by hand. No save at all is a fresh start, but a
+/// save from a newer version of the game is an error, so that it isn't
+/// replaced with one that has lost whatever the newer version added.
+pub fn load_save(path: &Path) -> Result<SaveData, String>
+{
+    match read_save(path)
+    {
+        Ok(Some(data)) => return Ok(data),
+        Ok(None) => (),
+        Err(ReadError::Newer(error)) => return Err(error),
+        Err(ReadError::Broken(error)) =>
+        {
+            eprintln!("{}", error);
+            if let Err(e) = fs::rename(path, sibling(path, "corrupt"))
+            {
+                return Err(format!("Could not move aside broken save '{}': {}", path.display(), e));
+            }
+        }
+    }
+
+    match read_save(&sibling(path, "bak"))
+    {
+        Ok(Some(data)) => Ok(data),
+        Ok(None) => Ok(SaveData::default()),
+        Err(ReadError::Newer(error)) | Err(ReadError::Broken(error)) => Err(error)
+    }
+}
+
+pub fn write_save(data: &SaveData, path: &Path) -> io::Result<()>
+{
+    let yaml = serde_yaml::to_string(data).map_err(|e| io::Error::other(e.to_string()))?;
+    write_atomically(path, &yaml, true)
+}
+
+/// Writes the whole file somewhere else first and only then swaps it in, so
+/// a crash part way through leaves the old one untouched. The old one can be
+/// kept alongside as a backup, as "<name>.bak".
+pub fn write_atomically(path: &Path, contents: &str, keep_backup: bool) -> io::Result<()>
+{
+    if let Some(dir) = path.parent()
+    {
+        fs::create_dir_all(dir)?;
+    }
+
+    let temp_path = sibling(path, "tmp");
+    {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.write_all(b"\n")?;
+        file.sync_all()?;
+    }
+
+    if keep_backup && path.exists()
+    {
+        fs::rename(path, sibling(path, "bak"))?;
+    }
+    fs::rename(&temp_path, path)
+}
+
+enum ReadError
+{
+    /// Written by a newer version of the game.
+    Newer(String),
+    Broken(String)
+}
+
+/// Just enough of a save to tell which version wrote it.
+#[derive(Deserialize)]
+struct SaveVersion
+{
+    version: u32
+}
+
+/// Ok(None) if there's no file there.
+fn read_save(path: &Path) -> Result<Option<SaveData>, ReadError>
+{
+    let yaml = match fs::read_to_string(path)
+    {
+        Ok(yaml) => yaml,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(ReadError::Broken(format!("Could not read save '{}': {}", path.display(), e)))
+    };
+
+    // The version is checked before the rest, which a newer game may have
+    // changed beyond recognition.
+    if let Ok(SaveVersion { version }) = serde_yaml::from_str(&yaml)
+    {
+        if version > SAVE_VERSION
+        {
+            return Err(ReadError::Newer(format!("Save '{}' is from a newer version of the game (format {}, this reads {})",
+                path.display(), version, SAVE_VERSION)));
+        }
+    }
+
+    serde_yaml::from_str(&yaml).map(Some).map_err(|e| ReadError::Broken(format!("Failed to parse save '{}': {}", path.display(), e)))
+}
+
+/// The save's path with another extension tacked on, as in "save.yaml.bak".
+fn sibling(path: &Path, extension: &str) -> PathBuf
+{
+    let mut name = path.file_name().map(|name| name.to_os_string()).unwrap_or_default();
+    name.push(".");
+    name.push(extension);
+    path.with_file_name(name)
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use progress::LevelResult;
+
+    #[test]
+    pub fn recovers_from_broken_saves()
+    {
+        let dir = env::temp_dir().join(format!("smallworld-save-{}", ::std::process::id()));
+        let path = dir.join(SAVE_NAME);
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(load_save(&path), Ok(SaveData::default()));
+
+        let mut first = SaveData::default();
+        first.progress.record("main/One", LevelResult::new(12, 1, 1, Some(12)));
+        write_save(&first, &path).unwrap();
+        assert_eq!(load_save(&path), Ok(first.clone()));
+
+        let mut second = first.clone();
+        second.last_pack = Some("main".to_string());
+        second.last_levels.insert("main".to_string(), 1);
+        write_save(&second, &path).unwrap();
+        assert_eq!(load_save(&path), Ok(second.clone()));
+
+        // A half-written save falls back to the one before it.
+        fs::write(&path, "version: 1\nprogress: { levels: {").unwrap();
+        assert_eq!(load_save(&path), Ok(first.clone()));
+        assert!(sibling(&path, "corrupt").exists());
+
+        fs::write(&path, "version: 99\nprogress: something else\n").unwrap();
+        assert!(load_save(&path).is_err());
+        assert!(path.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
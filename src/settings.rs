@@ -0,0 +1,197 @@
+//! The player's settings, kept in `settings.yaml` in the config directory
+//! next to the save. Anything missing from the file keeps its default, so
+//! the file only needs what's been changed.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use serde_yaml;
+
+use input::{default_bindings, Bindings};
+use save;
+
+pub const SETTINGS_NAME: &str = "settings.yaml";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Settings
+{
+    /// How many screen pixels each game pixel takes up.
+    pub scale: u32,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    /// From 0 to 1, for when there's sound.
+    pub volume: f32,
+    /// How fast things move, where 1 is normal speed.
+    pub animation_speed: f32,
+    /// How many moves pressed during another can wait their turn.
+    pub move_buffer: usize,
+    /// The name of one of the `PALETTES`.
+    pub palette: String,
+    /// Whether levels show their name, moves and objective over the top.
+    pub show_hud: bool,
+    /// The keys for each action. Actions left out keep their default keys.
+    pub keys: Bindings
+}
+
+/// Settings given on the command line, which apply only to this run.
+#[derive(Clone, Debug, Default)]
+pub struct Overrides
+{
+    pub scale: Option<u32>,
+    pub fullscreen: Option<bool>,
+    pub vsync: Option<bool>,
+    /// The window's size in screen pixels, instead of going by the scale.
+    pub window_size: Option<(u32, u32)>
+}
+
+pub struct Palette
+{
+    pub name: &'static str,
+    pub background: (f32, f32, f32, f32),
+    /// Multiplies the colour of every sprite.
+    pub tint: [f32; 4],
+    /// The colour of text, which has to stand out from the background.
+    pub text: [f32; 4]
+}
+
+pub const PALETTES: [Palette; 3] =
+[
+    Palette { name: "classic", background: (0.75, 0.75, 0.75, 1.0), tint: [1.0, 1.0, 1.0, 1.0], text: [0.1, 0.1, 0.12, 1.0] },
+    Palette { name: "night", background: (0.12, 0.13, 0.2, 1.0), tint: [0.7, 0.75, 1.0, 1.0], text: [0.85, 0.88, 1.0, 1.0] },
+    Palette { name: "sepia", background: (0.8, 0.72, 0.58, 1.0), tint: [1.0, 0.9, 0.72, 1.0], text: [0.3, 0.2, 0.1, 1.0] }
+];
+
+pub const MIN_SCALE: u32 = 1;
+pub const MAX_SCALE: u32 = 8;
+pub const MAX_MOVE_BUFFER: usize = 8;
+
+impl Default for Settings
+{
+    fn default() -> Self
+    {
+        Settings
+        {
+            scale: 4,
+            fullscreen: false,
+            vsync: true,
+            volume: 1.0,
+            animation_speed: 1.0,
+            move_buffer: 2,
+            palette: PALETTES[0].name.to_string(),
+            show_hud: true,
+            keys: default_bindings()
+        }
+    }
+}
+
+impl Settings
+{
+    /// The palette to draw with, falling back to the first if the name
+    /// isn't one of them.
+    pub fn palette(&self) -> &'static Palette
+    {
+        PALETTES.iter().find(|palette| palette.name == self.palette).unwrap_or(&PALETTES[0])
+    }
+
+    /// Clamps everything into range and fills in missing key bindings, for
+    /// settings edited by hand.
+    pub fn sanitise(&mut self)
+    {
+        for (action, keys) in default_bindings()
+        {
+            self.keys.entry(action).or_insert(keys);
+        }
+        self.scale = self.scale.clamp(MIN_SCALE, MAX_SCALE);
+        self.volume = self.volume.clamp(0.0, 1.0);
+        self.animation_speed = self.animation_speed.clamp(0.25, 4.0);
+        self.move_buffer = self.move_buffer.min(MAX_MOVE_BUFFER);
+    }
+}
+
+impl Overrides
+{
+    pub fn apply(&self, settings: &mut Settings)
+    {
+        if let Some(scale) = self.scale
+        {
+            settings.scale = scale;
+        }
+        if let Some(fullscreen) = self.fullscreen
+        {
+            settings.fullscreen = fullscreen;
+        }
+        if let Some(vsync) = self.vsync
+        {
+            settings.vsync = vsync;
+        }
+        settings.sanitise();
+    }
+}
+
+/// Loads the settings, using the defaults if there's no file. A broken file
+/// is reported and ignored, and gets replaced next time the settings are
+/// written.
+pub fn load_settings(path: &Path) -> Settings
+{
+    let yaml = match fs::read_to_string(path)
+    {
+        Ok(yaml) => yaml,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Settings::default(),
+        Err(e) =>
+        {
+            eprintln!("Could not read settings '{}': {}", path.display(), e);
+            return Settings::default();
+        }
+    };
+    match serde_yaml::from_str::<Settings>(&yaml)
+    {
+        Ok(mut settings) =>
+        {
+            settings.sanitise();
+            settings
+        },
+        Err(e) =>
+        {
+            eprintln!("Failed to parse settings '{}': {}", path.display(), e);
+            Settings::default()
+        }
+    }
+}
+
+pub fn write_settings(settings: &Settings, path: &Path) -> io::Result<()>
+{
+    let yaml = serde_yaml::to_string(settings).map_err(|e| io::Error::other(e.to_string()))?;
+    save::write_atomically(path, &yaml, false)
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use glium::glutin::VirtualKeyCode;
+    use input::{actions_for, Action};
+
+    #[test]
+    pub fn partial_settings()
+    {
+        let mut settings: Settings = serde_yaml::from_str("
+            scale: 20
+            palette: night
+            keys: { move_left: [Left, a] }
+        ").unwrap();
+        settings.sanitise();
+
+        assert_eq!(settings.scale, MAX_SCALE);
+        assert!(settings.vsync);
+        assert_eq!(settings.palette().name, "night");
+        assert_eq!(actions_for(&settings.keys, VirtualKeyCode::A), vec![Action::MoveLeft]);
+        assert_eq!(actions_for(&settings.keys, VirtualKeyCode::Right), vec![Action::MoveRight]);
+        assert_eq!(settings.keys[&Action::Quit], default_bindings()[&Action::Quit]);
+
+        let overrides = Overrides { scale: Some(2), vsync: Some(false), .. Overrides::default() };
+        overrides.apply(&mut settings);
+        assert_eq!((settings.scale, settings.vsync, settings.fullscreen), (2, false, false));
+    }
+}
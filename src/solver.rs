@@ -0,0 +1,221 @@
+//! Finds the shortest solution to a level by trying every move from every
+//! position the level can get into, using the game's own systems so that
+//! whatever it finds really works.
+
+use std::collections::{HashSet, VecDeque};
+use specs::{Gate, Join, Planner, World};
+
+use assets::{Level, Objective};
+use game_state::{build_world, player_moves, step_world, world_settled};
+use game_state::snapshot::Snapshot;
+use prefabs::PrefabTable;
+use systems::*;
+use systems::victory;
+use vectors::*;
+
+/// How long each simulated frame is. Long enough to be quick, short enough
+/// that nothing passes through anything else between frames.
+const STEP_DT: f64 = 0.1;
+/// A move that takes longer than this to settle is stuck somehow.
+const MAX_SETTLE_STEPS: usize = 100;
+
+const DIRECTIONS: [Vector2<f32>; 4] = [Vector2 { components: [-1.0, 0.0] }, Vector2 { components: [1.0, 0.0] },
+    Vector2 { components: [0.0, 1.0] }, Vector2 { components: [0.0, -1.0] }];
+
+#[derive(Debug, PartialEq)]
+pub enum Solution
+{
+    /// The fewest moves that finish the level.
+    Solved(Vec<Vector2<f32>>),
+    /// Every position the level can get into has been tried.
+    Unsolvable,
+    /// Gave up after trying this many positions.
+    GaveUp(usize)
+}
+
+enum Outcome
+{
+    Won,
+    Lost,
+    Continues
+}
+
+/// Tries at most `max_states` positions before giving up.
+pub fn solve(level: &Level, prefabs: &PrefabTable, max_states: usize) -> Solution
+{
+    let mut planner = Planner::new(build_world(level, prefabs));
+    let mut seen = HashSet::new();
+    seen.insert(state_key(planner.mut_world()));
+
+    let mut queue = VecDeque::new();
+    queue.push_back((Snapshot::take(planner.mut_world()), Vec::new()));
+
+    while let Some((snapshot, path)) = queue.pop_front()
+    {
+        for dir in &DIRECTIONS
+        {
+            snapshot.clone().restore(planner.mut_world());
+            let moves_before = player_moves(planner.mut_world());
+
+            let outcome = play_move(&mut planner, *dir, level.meta.objective);
+            if player_moves(planner.mut_world()) == moves_before
+            {
+                continue;
+            }
+
+            let mut next_path = path.clone();
+            next_path.push(*dir);
+            match outcome
+            {
+                Outcome::Won => return Solution::Solved(next_path),
+                Outcome::Lost => continue,
+                Outcome::Continues => ()
+            }
+
+            if seen.insert(state_key(planner.mut_world()))
+            {
+                if seen.len() > max_states
+                {
+                    return Solution::GaveUp(max_states);
+                }
+                queue.push_back((Snapshot::take(planner.mut_world()), next_path));
+            }
+        }
+    }
+    Solution::Unsolvable
+}
+
+/// Whether `dirs` finishes the level, making each move once everything's
+/// stopped moving from the last.
+pub fn check_moves(level: &Level, prefabs: &PrefabTable, dirs: &[Vector2<f32>]) -> bool
+{
+    let mut planner = Planner::new(build_world(level, prefabs));
+    for dir in dirs
+    {
+        match play_move(&mut planner, *dir, level.meta.objective)
+        {
+            Outcome::Won => return true,
+            Outcome::Lost => return false,
+            Outcome::Continues => ()
+        }
+    }
+    false
+}
+
+/// Makes a move and waits for everything to stop, checking after every
+/// frame the way the game does.
+fn play_move(planner: &mut Planner<()>, dir: Vector2<f32>, objective: Objective) -> Outcome
+{
+    let mut step_dir = dir;
+    for _ in 0..MAX_SETTLE_STEPS
+    {
+        step_world(planner, step_dir, STEP_DT);
+        step_dir = vec2(0.0, 0.0);
+
+        let world = planner.mut_world();
+        let won = match objective
+        {
+            Objective::Exit => victory::determine_victory_from_goal(world),
+            Objective::Buttons => victory::determine_victory_from_buttons(world)
+        };
+        if won
+        {
+            return Outcome::Won;
+        }
+        if victory::determine_gameover_from_hazard(world)
+        {
+            return Outcome::Lost;
+        }
+        if world_settled(world)
+        {
+            return Outcome::Continues;
+        }
+    }
+    Outcome::Continues
+}
+
+type StateKey = (Vec<Vector2<i32>>, Vec<Vec<Vector2<i32>>>, Vec<bool>);
+
+/// What tells one position in a level from another: where everything that
+/// moves is, the stalker's memory of the player's path and what's been
+/// collected.
+fn state_key(world: &World) -> StateKey
+{
+    let (position, motion) = (world.read::<Position>().pass(), world.read::<Motion>().pass());
+    let positions = (&position, &motion).join().map(|(position, _)| position.0.round_i32()).collect();
+    let trackers = (&world.read::<PlayerTracker>().pass()).join().map(|tracker| tracker.steps.iter().cloned().collect()).collect();
+    let collected = (&world.read::<Collectible>().pass()).join().map(|collectible| collectible.0).collect();
+    (positions, trackers, collected)
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use serde_yaml;
+
+    use assets::parse_levels;
+
+    #[test]
+    pub fn solves_the_first_level()
+    {
+        let prefabs: PrefabTable = serde_yaml::from_str(include_str!("../assets/entities.yaml")).unwrap();
+        let level = parse_levels(include_str!("../assets/packs/main/levels.yaml"), &prefabs).remove(0);
+
+        match solve(&level, &prefabs, 5000)
+        {
+            Solution::Solved(dirs) =>
+            {
+                assert!(check_moves(&level, &prefabs, &dirs));
+                assert!(!check_moves(&level, &prefabs, &dirs[..dirs.len() - 1]));
+            },
+            other => panic!("Expected a solution, got {:?}", other)
+        }
+    }
+
+    /// Plays `dirs` the way the game does, a frame at a time and each as
+    /// soon as it can be made, and keeps the ones that moved the player.
+    fn play_live(level: &Level, prefabs: &PrefabTable, dirs: &[Vector2<f32>]) -> Vec<Vector2<f32>>
+    {
+        let mut planner = Planner::new(build_world(level, prefabs));
+        let (mut pending, mut recorded) = (dirs.iter(), Vec::new());
+        for _ in 0..dirs.len() * MAX_SETTLE_STEPS
+        {
+            let moves_before = player_moves(planner.mut_world());
+            let dir = if world_settled(planner.mut_world())
+            {
+                pending.next().cloned().unwrap_or(vec2(0.0, 0.0))
+            }
+            else
+            {
+                vec2(0.0, 0.0)
+            };
+            step_world(&mut planner, dir, 1.0 / 60.0);
+            if player_moves(planner.mut_world()) > moves_before
+            {
+                recorded.push(dir);
+            }
+        }
+        recorded
+    }
+
+    #[test]
+    pub fn recorded_moves_check_out()
+    {
+        let prefabs: PrefabTable = serde_yaml::from_str(include_str!("../assets/entities.yaml")).unwrap();
+        let levels = parse_levels(include_str!("../assets/packs/main/levels.yaml"), &prefabs);
+
+        for level in levels.iter().take(3)
+        {
+            let dirs = match solve(level, &prefabs, 20000)
+            {
+                Solution::Solved(dirs) => dirs,
+                other => panic!("Expected a solution, got {:?}", other)
+            };
+            let recorded = play_live(level, &prefabs, &dirs);
+            assert_eq!(recorded, dirs);
+            assert!(check_moves(level, &prefabs, &recorded));
+        }
+    }
+}
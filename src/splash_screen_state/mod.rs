@@ -1,7 +1,7 @@
 use glium::{DrawParameters, DepthTest, Depth, Blend};
 use glium::uniforms::{Sampler, MinifySamplerFilter, MagnifySamplerFilter, SamplerWrapFunction};
 
-use assets::{get_asset_string, get_asset_bytes};
+use assets::{get_asset_string, get_asset_bytes, read_asset_bytes};
 use game::Game;
 use rendering::*;
 use state::*;
@@ -11,6 +11,8 @@ pub struct SplashScreenState
     shader: Shader,
     mesh: Mesh,
     splash_texture: Texture,
+    /// The splash image's height over its width.
+    texture_aspect: f32,
     time: f64
 }
 
@@ -18,16 +20,16 @@ impl State for SplashScreenState
 {
     fn new(display: &Display, game: &mut Game) -> Self
     {
-        let shader = load_shader(display, &get_asset_string("shaders/splash.vs"), &get_asset_string("shaders/splash.fs"));
+        let shader = load_shader(display, get_asset_string("shaders/splash.vs"), get_asset_string("shaders/splash.fs"));
         let mesh = quad_mesh(display);
-        let splash_texture_name = if game.current_state == StateType::EndingState { "ending_screen.png" } else { "splash_screen.png" };
-        let splash_texture = load_texture(display, &get_asset_bytes(splash_texture_name)).0;
+        let (splash_texture, (width, height)) = load_texture(display, &get_asset_bytes(splash_texture_name(game)));
 
         SplashScreenState
         {
             shader: shader,
             mesh: mesh,
             splash_texture: splash_texture,
+            texture_aspect: height as f32 / width as f32,
             time: 0.0
         }
     }
@@ -41,7 +43,8 @@ impl State for SplashScreenState
         {
             game.current_state = match game.current_state
             {
-                StateType::SplashScreen => StateType::GameState,
+                StateType::SplashScreen if game.packs.len() > 1 => StateType::PackSelect,
+                StateType::SplashScreen => StateType::LevelSelect,
                 _ => StateType::SplashScreen
             }
         }
@@ -49,7 +52,7 @@ impl State for SplashScreenState
         !exiting_state
     }
 
-    fn draw(&mut self, target: &mut Frame, _game: &mut Game)
+    fn draw(&mut self, target: &mut Canvas, _game: &mut Game)
     {
         target.clear_color_srgb_and_depth((0.75, 0.75, 0.75, 1.0), 1.0);
 
@@ -57,6 +60,7 @@ impl State for SplashScreenState
             .minify_filter(MinifySamplerFilter::Nearest)
             .magnify_filter(MagnifySamplerFilter::Nearest)
             .wrap_function(SamplerWrapFunction::Clamp);
+        let (width, height) = target.get_dimensions();
 
         target.draw(
             &self.mesh.0,
@@ -64,7 +68,8 @@ impl State for SplashScreenState
             &self.shader,
             &uniform!
             {
-                colormap: colormap
+                colormap: colormap,
+                aspect: self.texture_aspect * width as f32 / height as f32
             },
             &DrawParameters
             {
@@ -78,4 +83,19 @@ impl State for SplashScreenState
                 .. Default::default()
             }).unwrap();
     }
+
+    fn reload_graphics(&mut self, display: &Display, game: &mut Game) -> Result<(), String>
+    {
+        let shader = try_load_shader_asset(display, "shaders/splash")?;
+        let (splash_texture, (width, height)) = try_load_texture(display, &read_asset_bytes(splash_texture_name(game))?)?;
+        self.shader = shader;
+        self.splash_texture = splash_texture;
+        self.texture_aspect = height as f32 / width as f32;
+        Ok(())
+    }
+}
+
+fn splash_texture_name(game: &Game) -> &'static str
+{
+    if game.current_state == StateType::EndingState { "ending_screen.png" } else { "splash_screen.png" }
 }
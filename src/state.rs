@@ -1,4 +1,4 @@
-pub use glium::{Frame, Surface};
+pub use glium::Surface;
 
 use game::Game;
 use rendering::*;
@@ -7,13 +7,24 @@ pub trait State
 {
     fn new(display: &Display, game: &mut Game) -> Self;
     fn update(&mut self, dt: f64, game: &mut Game) -> bool;
-    fn draw(&mut self, target: &mut Frame, game: &mut Game);
+    fn draw(&mut self, target: &mut Canvas, game: &mut Game);
+
+    /// Picks up changed shaders and textures. States that keep any should
+    /// load the new ones, keeping the old ones if that fails.
+    fn reload_graphics(&mut self, _display: &Display, _game: &mut Game) -> Result<(), String>
+    {
+        Ok(())
+    }
 }
 
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum StateType
 {
     SplashScreen,
     GameState,
-    EndingState
+    EndingState,
+    Editor,
+    PackSelect,
+    LevelSelect,
+    Options
 }
@@ -0,0 +1,234 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use serde_yaml;
+use specs::{RunArg, Join};
+
+use assets::read_asset_string;
+use systems::{Position, Sprite, Motion, Player, PlayerTracker, ButtonGate};
+use vectors::*;
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum Playback
+{
+    #[default]
+    #[serde(rename = "loop")]
+    Loop,
+    /// Plays through once and stays on the last frame.
+    #[serde(rename = "once")]
+    Once
+}
+
+/// A run of atlas regions, each shown for `frame_time` seconds.
+#[derive(Deserialize, Clone, Debug)]
+pub struct Clip
+{
+    pub frames: Vec<[u32; 2]>,
+    pub frame_time: f32,
+    #[serde(default)]
+    pub playback: Playback
+}
+
+impl Clip
+{
+    pub fn length(&self) -> f32
+    {
+        self.frames.len() as f32 * self.frame_time
+    }
+
+    pub fn frame_at(&self, time: f32) -> Vector2<u32>
+    {
+        let count = self.frames.len();
+        let index = (time / self.frame_time).max(0.0) as usize;
+        let index = match self.playback
+        {
+            Playback::Loop => index % count,
+            Playback::Once => index.min(count - 1)
+        };
+        vec2(self.frames[index][0], self.frames[index][1])
+    }
+}
+
+/// One entity's clips, by the name of the state they show.
+pub type ClipSet = BTreeMap<String, Clip>;
+
+/// Every clip set in `animations.yaml`, by name.
+#[derive(Deserialize, Default)]
+pub struct AnimationTable
+{
+    pub animations: HashMap<String, ClipSet>
+}
+
+/// Picks which of an entity's clips to show from what it's doing, and
+/// which frame of it.
+#[derive(Clone)]
+pub struct Animation
+{
+    pub clips: ClipSet,
+    /// The clip playing, which is empty until the first is chosen.
+    pub current: String,
+    pub time: f32
+}
+component!(Animation);
+
+impl Animation
+{
+    pub fn new(clips: ClipSet) -> Self
+    {
+        Animation { clips: clips, current: String::new(), time: 0.0 }
+    }
+
+    /// Switches to the first of `names` there's a clip for, unless it's
+    /// already playing. The first clip chosen starts at its end, so a gate
+    /// that starts closed doesn't play closing.
+    pub fn play(&mut self, names: &[&str])
+    {
+        let name = match names.iter().find(|name| self.clips.contains_key(**name))
+        {
+            Some(name) => *name,
+            None => return
+        };
+        if name == self.current
+        {
+            return;
+        }
+
+        self.time = if self.current.is_empty() { self.clips[name].length() } else { 0.0 };
+        self.current = name.to_string();
+    }
+
+    pub fn frame(&self) -> Option<Vector2<u32>>
+    {
+        self.clips.get(&self.current).map(|clip| clip.frame_at(self.time))
+    }
+}
+
+pub fn load_animations<P>(path: P) -> Result<AnimationTable, String>
+    where P: AsRef<Path>
+{
+    let yaml = read_asset_string(path)?;
+    let table: AnimationTable = serde_yaml::from_str(&yaml).map_err(|e| format!("Failed to parse animations: {}", e))?;
+    for (name, clips) in &table.animations
+    {
+        for (clip_name, clip) in clips
+        {
+            if clip.frames.is_empty() || clip.frame_time <= 0.0
+            {
+                return Err(format!("Clip '{}' of animation '{}' needs frames and a frame time above zero", clip_name, name));
+            }
+        }
+    }
+    Ok(table)
+}
+
+fn direction_name(dir: Vector2<f32>) -> &'static str
+{
+    let (x, y) = (dir.components[0], dir.components[1]);
+    if x.abs() >= y.abs()
+    {
+        if x < 0.0 { "left" } else { "right" }
+    }
+    else if y < 0.0 { "down" } else { "up" }
+}
+
+/// Chooses each animated entity's clip: walking or pushing in the direction
+/// it's going, reaching for a player next to it, a gate opening or closing,
+/// or idle. States without a clip of their own fall back to plainer ones.
+pub fn choose_clips(arg: RunArg)
+{
+    let (entities, mut animation, position, motion, player, tracker, gate) = arg.fetch(|w|
+        (w.entities(), w.write::<Animation>(), w.read::<Position>(), w.read::<Motion>(), w.read::<Player>(), w.read::<PlayerTracker>(), w.read::<ButtonGate>()));
+
+    let moving: Vec<(Vector2<i32>, Vector2<i32>)> = (&motion).join()
+        .filter_map(|motion| motion.destination.map(|destination| (destination.position.round_i32(), destination.direction.round_i32())))
+        .collect();
+    let player_tiles: Vec<Vector2<i32>> = (&position, &player).join().map(|(position, _)| position.0.round_i32()).collect();
+
+    for (entity, animation, position) in (&entities, &mut animation, &position).join()
+    {
+        if let Some(gate) = gate.get(entity)
+        {
+            animation.play(if gate.open { &["open", "idle"] } else { &["closed", "idle"] });
+            continue;
+        }
+
+        let destination = motion.get(entity).and_then(|motion| if motion.delay_remaining > 0.0 { None } else { motion.destination });
+        if let Some(destination) = destination
+        {
+            let name = direction_name(destination.direction);
+            let (walk, push) = (format!("walk_{}", name), format!("push_{}", name));
+            let (tile, dir) = (destination.position.round_i32(), destination.direction.round_i32());
+            let pushing = moving.contains(&(tile + dir, dir));
+            if pushing
+            {
+                animation.play(&[&push, "push", &walk, "walk", "idle"]);
+            }
+            else
+            {
+                animation.play(&[&walk, "walk", "idle"]);
+            }
+            continue;
+        }
+
+        let tile = position.0.round_i32();
+        let player_nearby = player_tiles.iter().any(|player_tile|
+        {
+            let offset = *player_tile - tile;
+            offset.dot(offset) == 1
+        });
+        if tracker.get(entity).is_some() && player_nearby
+        {
+            animation.play(&["reach", "idle"]);
+        }
+        else
+        {
+            animation.play(&["idle"]);
+        }
+    }
+}
+
+pub fn animate_sprites(arg: RunArg, dt: f64)
+{
+    let (mut animation, mut sprite) = arg.fetch(|w| (w.write::<Animation>(), w.write::<Sprite>()));
+
+    for (animation, sprite) in (&mut animation, &mut sprite).join()
+    {
+        animation.time += dt as f32;
+        if let Some(frame) = animation.frame()
+        {
+            sprite.region = frame;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    pub fn clips_fall_back_and_hold()
+    {
+        let clips: ClipSet = serde_yaml::from_str("
+            idle: { frames: [[0, 0]], frame_time: 1.0 }
+            walk: { frames: [[1, 0], [0, 0]], frame_time: 0.5 }
+            open: { frames: [[4, 4], [1, 4]], frame_time: 0.5, playback: once }
+        ").unwrap();
+        let mut animation = Animation::new(clips);
+
+        // The first clip starts settled; later ones from the top.
+        animation.play(&["walk_left", "walk", "idle"]);
+        animation.play(&["open"]);
+        assert_eq!(animation.frame(), Some(vec2(4, 4)));
+
+        animation.time = 5.0;
+        assert_eq!(animation.frame(), Some(vec2(1, 4)));
+
+        animation.play(&["walk"]);
+        animation.time = 1.25;
+        assert_eq!(animation.frame(), Some(vec2(1, 0)));
+
+        animation.play(&["reach"]);
+        assert_eq!(animation.current, "walk");
+    }
+}
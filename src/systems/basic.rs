@@ -1,4 +1,5 @@
 use vectors::*;
 
+#[derive(Clone)]
 pub struct Position(pub Vector2<f32>);
 component!(Position);
@@ -3,10 +3,19 @@ use specs::{RunArg, Join};
 use systems::{Position, Motion, Collision, Sprite};
 use vectors::*;
 
+#[derive(Clone)]
 pub struct Button(pub bool);
 component!(Button);
 
-pub struct ButtonGate(pub bool);
+#[derive(Clone)]
+pub struct ButtonGate
+{
+    pub open: bool,
+    pub links: Vec<Vector2<i32>>,
+    pub closed_collision: Collision,
+    pub closed_region: Vector2<u32>,
+    pub open_region: Vector2<u32>
+}
 component!(ButtonGate);
 
 
@@ -33,22 +42,26 @@ pub fn check_button_presses(arg: RunArg)
 
 pub fn open_and_close_gates(arg: RunArg)
 {
-    let (mut collision, mut gate, button) = arg.fetch(|w| (w.write::<Collision>(), w.write::<ButtonGate>(), w.read::<Button>()));
+    let (mut collision, mut gate, button, position) = arg.fetch(|w| (w.write::<Collision>(), w.write::<ButtonGate>(), w.read::<Button>(), w.read::<Position>()));
 
-    let mut button_pressed = false;
-    for button in (&button).join()
-    {
-        button_pressed |= button.0;
-    }
+    let pressed_buttons: Vec<Vector2<i32>> = (&button, &position).join()
+        .filter(|&(button, _)| button.0)
+        .map(|(_, position)| position.0.round_i32())
+        .collect();
 
     for (collision, gate) in (&mut collision, &mut gate).join()
     {
-        gate.0 = button_pressed;
-        *collision = match button_pressed
+        let open = if gate.links.is_empty()
         {
-            true => Collision::Passable,
-            false => Collision::Obstacle
+            !pressed_buttons.is_empty()
         }
+        else
+        {
+            gate.links.iter().any(|link| pressed_buttons.contains(link))
+        };
+
+        gate.open = open;
+        *collision = if open { Collision::passable() } else { gate.closed_collision }
     }
 }
 
@@ -58,10 +71,6 @@ pub fn update_gate_sprites(arg: RunArg)
 
     for (sprite, gate) in (&mut sprite, &gate).join()
     {
-        sprite.region = match gate.0
-        {
-            true => vec2(1, 4),
-            false => vec2(0, 4)
-        }
+        sprite.region = if gate.open { gate.open_region } else { gate.closed_region }
     }
 }
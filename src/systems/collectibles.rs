@@ -0,0 +1,41 @@
+use specs::{RunArg, Join};
+
+use systems::{Position, Player, Sprite};
+use systems::visual::BLANK_REGION;
+
+#[derive(Clone)]
+pub struct Collectible(pub bool);
+component!(Collectible);
+
+
+pub fn collect_items(arg: RunArg)
+{
+    let (mut collectible, mut player, position) = arg.fetch(|w| (w.write::<Collectible>(), w.write::<Player>(), w.read::<Position>()));
+
+    for (player_pos, player) in (&position, &mut player).join()
+    {
+        let player_tile = player_pos.0.round_i32();
+
+        for (item_pos, collectible) in (&position, &mut collectible).join()
+        {
+            if !collectible.0 && item_pos.0.round_i32() == player_tile
+            {
+                collectible.0 = true;
+                player.collected += 1;
+            }
+        }
+    }
+}
+
+pub fn update_collectible_sprites(arg: RunArg)
+{
+    let (mut sprite, collectible) = arg.fetch(|w| (w.write::<Sprite>(), w.read::<Collectible>()));
+
+    for (sprite, collectible) in (&mut sprite, &collectible).join()
+    {
+        if collectible.0
+        {
+            sprite.region = BLANK_REGION;
+        }
+    }
+}
@@ -1,11 +1,17 @@
+pub mod animation;
 pub mod basic;
 pub mod buttons;
+pub mod collectibles;
 pub mod motion;
+pub mod particles;
 pub mod victory;
 pub mod visual;
 
+pub use self::animation::{Animation};
 pub use self::basic::{Position};
 pub use self::buttons::{Button, ButtonGate};
-pub use self::motion::{Motion, Player, Collision, PlayerTracker};
+pub use self::collectibles::{Collectible};
+pub use self::particles::{Emitter};
+pub use self::motion::{Motion, Player, Collision, PlayerTracker, PLAYER_CLASS, STALKER_CLASS, BLOCK_CLASS, ALL_CLASSES};
 pub use self::victory::{Hazard, Goal};
 pub use self::visual::{Sprite};
@@ -5,7 +5,7 @@ use specs::{RunArg, Join};
 use systems::{Position};
 use vectors::*;
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Motion
 {
     pub destination: Option<Destination>,
@@ -39,23 +39,107 @@ pub struct Destination
     pub direction: Vector2<f32>
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Player
 {
-    pub moves: u32
+    pub moves: u32,
+    pub collected: u32
 }
 component!(Player);
 
-#[derive(Copy, Clone, PartialEq, Eq)]
-pub enum Collision
+pub const PLAYER_CLASS: u32 = 1 << 0;
+pub const STALKER_CLASS: u32 = 1 << 1;
+pub const BLOCK_CLASS: u32 = 1 << 2;
+pub const ALL_CLASSES: u32 = PLAYER_CLASS | STALKER_CLASS | BLOCK_CLASS;
+
+/// A row of the collision matrix. Every mover belongs to a class, and each
+/// entity says which classes it keeps out of its tile and which classes can
+/// shove it along instead.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Collision
 {
-    Passable,
-    BlocksPush,
-    Pushable,
-    Obstacle
+    pub class: u32,
+    pub blocks: u32,
+    pub pushed_by: u32
 }
 component!(Collision);
 
+impl Collision
+{
+    pub fn passable() -> Self
+    {
+        Collision { class: 0, blocks: 0, pushed_by: 0 }
+    }
+
+    pub fn obstacle() -> Self
+    {
+        Collision { class: 0, blocks: ALL_CLASSES, pushed_by: 0 }
+    }
+
+    pub fn blocks_class(&self, class: u32) -> bool
+    {
+        self.blocks & class != 0
+    }
+
+    pub fn pushable_by(&self, class: u32) -> bool
+    {
+        self.pushed_by & class != 0
+    }
+}
+
+pub fn class_mask(name: &str) -> Option<u32>
+{
+    match name
+    {
+        "player" => Some(PLAYER_CLASS),
+        "stalker" => Some(STALKER_CLASS),
+        "block" => Some(BLOCK_CLASS),
+        "all" => Some(ALL_CLASSES),
+        "none" => Some(0),
+        _ => None
+    }
+}
+
+type Colliders = Vec<(Vector2<i32>, Collision)>;
+
+fn colliders<'a, P, C>(positions: P, collisions: C) -> Colliders
+    where P: Join<Type=&'a Position>, C: Join<Type=&'a Collision>
+{
+    (positions, collisions).join().map(|(position, collision)| (position.0.round_i32(), *collision)).collect()
+}
+
+/// The one place that decides whether something of `class` may step into
+/// `dest_tile`, pushing whatever is there along `dir` if it's allowed to.
+fn move_obstructed(colliders: &Colliders, class: u32, dest_tile: Vector2<i32>, dir: Vector2<i32>) -> bool
+{
+    for &(tile, collision) in colliders
+    {
+        if tile != dest_tile
+        {
+            continue;
+        }
+
+        if collision.pushable_by(class)
+        {
+            if push_obstructed(colliders, collision.class, dest_tile + dir)
+            {
+                return true;
+            }
+        }
+        else if collision.blocks_class(class)
+        {
+            return true;
+        }
+    }
+    false
+}
+
+fn push_obstructed(colliders: &Colliders, class: u32, push_dest: Vector2<i32>) -> bool
+{
+    colliders.iter().any(|&(tile, collision)| tile == push_dest && collision.blocks_class(class))
+}
+
+#[derive(Clone)]
 pub struct PlayerTracker
 {
     pub steps: VecDeque<Vector2<i32>>,
@@ -77,7 +161,9 @@ pub fn player_controls(arg: RunArg, dir: Vector2<f32>)
 {
     let (mut motion, positions, mut player, collisions) = arg.fetch(|w| (w.write::<Motion>(), w.read::<Position>(), w.write::<Player>(), w.read::<Collision>()));
 
-    for (motion, position, player) in (&mut motion, &positions, &mut player).join()
+    let colliders = colliders(&positions, &collisions);
+
+    for (motion, position, player, collision) in (&mut motion, &positions, &mut player, &collisions).join()
     {
         if motion.destination.is_some()
         {
@@ -88,36 +174,7 @@ pub fn player_controls(arg: RunArg, dir: Vector2<f32>)
         let dest = pos + dir;
         let dest_tile = dest.round_i32();
 
-        let obstructed = {
-            let mut obstructed = false;
-            for (position, collision) in (&positions, &collisions).join()
-            {
-                let pos_tile = position.0.round_i32();
-                if pos_tile == dest_tile
-                {
-                    if collision == &Collision::Obstacle
-                    {
-                        obstructed = true;
-                        break;
-                    }
-                    else if collision == &Collision::Pushable
-                    {
-                        // Fucked up bad. Have to check for pushing things in like three places
-                        let push_dest = dest_tile + dir.round_i32();
-                        for (position, collision) in (&positions, &collisions).join()
-                        {
-                            if position.0.round_i32() == push_dest && collision != &Collision::Passable
-                            {
-                                obstructed = true;
-                            }
-                        }
-                    }
-                }
-            }
-            obstructed
-        };
-
-        if (pos.round_i32() == dest_tile) || obstructed
+        if (pos.round_i32() == dest_tile) || move_obstructed(&colliders, collision.class, dest_tile, dir.round_i32())
         {
             continue;
         }
@@ -174,8 +231,9 @@ pub fn track_player(arg: RunArg)
         player_moves = player.moves;
     }
 
-    // Spaghetti incoming
-    for (tracker, motion, position) in (&mut tracker, &mut motion, &positions).join()
+    let colliders = colliders(&positions, &collisions);
+
+    for (tracker, motion, position, collision) in (&mut tracker, &mut motion, &positions, &collisions).join()
     {
         if tracker.steps.back() != Some(&player_pos)
         {
@@ -188,54 +246,15 @@ pub fn track_player(arg: RunArg)
             {
                 let pos = position.0;
                 let tile_pos = pos.round_i32();
-                let mut looking_for_move = true;
 
-                while looking_for_move
+                while let Some(next_step) = tracker.steps.pop_front()
                 {
-                    let mut failed_to_move = false;
-
-                    if let Some(next_step) = tracker.steps.pop_front()
-                    {
-                        let dir = next_step - tile_pos;
-                        if dir.dot(dir) == 1
-                        {
-                            for (position, collision) in (&positions, &collisions).join()
-                            {
-                                if position.0.round_i32() == next_step
-                                {
-                                    if collision == &Collision::Obstacle
-                                    {
-                                        failed_to_move = true;
-                                    }
-                                    else if collision == &Collision::Pushable
-                                    {
-                                        let push_dest = next_step + dir;
-                                        for (position, collision) in (&positions, &collisions).join()
-                                        {
-                                            if position.0.round_i32() == push_dest && collision != &Collision::Passable
-                                            {
-                                                failed_to_move = true;
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        else
-                        {
-                            failed_to_move = true;
-                        }
-
-                        if !failed_to_move
-                        {
-                            let dest = next_step.to_f32();
-                            motion.move_from_to(pos, dest);
-                            tracker.moves += 1;
-                            looking_for_move = false;
-                        }
-                    }
-                    else
+                    let dir = next_step - tile_pos;
+                    if dir.dot(dir) == 1 && !move_obstructed(&colliders, collision.class, next_step, dir)
                     {
+                        let dest = next_step.to_f32();
+                        motion.move_from_to(pos, dest);
+                        tracker.moves += 1;
                         break;
                     }
                 }
@@ -252,9 +271,10 @@ pub fn push_stuff(arg: RunArg)
 {
     let (entities, mut motion, position, collision) = arg.fetch(|w| (w.entities(), w.write::<Motion>(), w.read::<Position>(), w.read::<Collision>()));
 
+    let colliders = colliders(&position, &collision);
     let mut pushes = Vec::new();
 
-    for motion_a in (&motion).join()
+    for (motion_a, collision_a) in (&motion, &collision).join()
     {
         if let Some(destination) = motion_a.destination
         {
@@ -265,9 +285,9 @@ pub fn push_stuff(arg: RunArg)
 
             let dest_tile = destination.position.round_i32();
 
-            for (entity, motion_b, position, collision) in (&entities, &motion, &position, &collision).join()
+            for (entity, motion_b, position, collision_b) in (&entities, &motion, &position, &collision).join()
             {
-                if collision != &Collision::Pushable || motion_b.destination.is_some()
+                if !collision_b.pushable_by(collision_a.class) || motion_b.destination.is_some()
                 {
                     continue;
                 }
@@ -277,22 +297,15 @@ pub fn push_stuff(arg: RunArg)
                 {
                     let push_dir = destination.direction;
                     let push_dest = position.0 + push_dir;
-                    pushes.push((entity, push_dest, push_dir));
+                    pushes.push((entity, collision_b.class, push_dest, push_dir));
                 }
             }
         }
     }
 
-    for (entity, push_dest, push_dir) in pushes
+    for (entity, class, push_dest, push_dir) in pushes
     {
-        let mut blocked = false;
-        for (position, collision) in (&position, &collision).join()
-        {
-            let pos = position.0.round_i32();
-            blocked |= pos == push_dest.round_i32() && collision != &Collision::Passable;
-        }
-
-        if !blocked
+        if !push_obstructed(&colliders, class, push_dest.round_i32())
         {
             if let Some(motion) = motion.get_mut(entity)
             {
@@ -301,3 +314,34 @@ pub fn push_stuff(arg: RunArg)
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn block() -> Collision
+    {
+        Collision { class: BLOCK_CLASS, blocks: ALL_CLASSES, pushed_by: PLAYER_CLASS }
+    }
+
+    #[test]
+    pub fn masks_are_per_class()
+    {
+        let grate = Collision { class: 0, blocks: STALKER_CLASS, pushed_by: 0 };
+        let colliders = vec![(vec2(1, 0), grate)];
+        assert!(!move_obstructed(&colliders, PLAYER_CLASS, vec2(1, 0), vec2(1, 0)));
+        assert!(move_obstructed(&colliders, STALKER_CLASS, vec2(1, 0), vec2(1, 0)));
+    }
+
+    #[test]
+    pub fn pushing_depends_on_what_is_behind()
+    {
+        let crawlspace = Collision { class: 0, blocks: BLOCK_CLASS, pushed_by: 0 };
+        let colliders = vec![(vec2(1, 0), block()), (vec2(2, 0), crawlspace)];
+        assert!(move_obstructed(&colliders, PLAYER_CLASS, vec2(1, 0), vec2(1, 0)));
+        assert!(!move_obstructed(&colliders, PLAYER_CLASS, vec2(1, 0), vec2(0, 1)));
+        assert!(move_obstructed(&colliders, STALKER_CLASS, vec2(1, 0), vec2(0, 1)));
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::path::Path;
+use serde_yaml;
+use specs::{RunArg, Join, World, Gate};
+
+use assets::read_asset_string;
+use rendering::ParticleSprite;
+use systems::{Position, Motion, Player, Button};
+use vectors::*;
+
+/// A burst of particles, as described in `particles.yaml`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct ParticleEffect
+{
+    pub region: [u32; 2],
+    pub count: u32,
+    /// Seconds each particle lasts.
+    pub lifetime: f32,
+    /// Tiles a second, picked at random between the two.
+    pub speed: [f32; 2],
+    /// Tiles a second per second, downwards.
+    #[serde(default)]
+    pub gravity: f32,
+    /// How many radians either side of the way they're sent particles can
+    /// go. Bursts that aren't sent any way go every way.
+    #[serde(default)]
+    pub spread: f32,
+    /// How far from where it's sent each starts, in tiles.
+    #[serde(default)]
+    pub offset: f32,
+    /// Tiles across, at the start and end of a particle's life.
+    pub size: [f32; 2],
+    /// The colour at the start and end of a particle's life.
+    pub colors: [[f32; 4]; 2]
+}
+
+/// Every effect in `particles.yaml`, by name.
+#[derive(Deserialize, Default)]
+pub struct ParticleTable
+{
+    pub effects: HashMap<String, ParticleEffect>
+}
+
+pub fn load_particles<P>(path: P) -> Result<ParticleTable, String>
+    where P: AsRef<Path>
+{
+    let yaml = read_asset_string(path)?;
+    let table: ParticleTable = serde_yaml::from_str(&yaml).map_err(|e| format!("Failed to parse particles: {}", e))?;
+    for (name, effect) in &table.effects
+    {
+        if effect.count == 0 || effect.lifetime <= 0.0
+        {
+            return Err(format!("Particle effect '{}' needs a count and a lifetime above zero", name));
+        }
+    }
+    Ok(table)
+}
+
+/// What sets an emitter off.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trigger
+{
+    /// Starting to move, sent out behind.
+    #[serde(rename = "move")]
+    Move,
+    /// A button being pressed.
+    #[serde(rename = "press")]
+    Press,
+    /// The player being caught.
+    #[serde(rename = "caught")]
+    Caught
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Particle
+{
+    pub position: Vector2<f32>,
+    pub velocity: Vector2<f32>,
+    pub age: f32
+}
+
+/// Sends out bursts of an effect from its entity when triggered. The
+/// particles are left where they're sent, rather than following it.
+#[derive(Clone)]
+pub struct Emitter
+{
+    pub effect: ParticleEffect,
+    pub trigger: Trigger,
+    pub particles: Vec<Particle>,
+    /// Whether the trigger held last frame, so holding it only bursts once.
+    pub active: bool,
+    seed: u32
+}
+component!(Emitter);
+
+impl Emitter
+{
+    /// An emitter on the tile at `position`, which picks its seed so that
+    /// emitters on different tiles don't all send out the same burst.
+    pub fn new(effect: ParticleEffect, trigger: Trigger, position: Vector2<f32>) -> Self
+    {
+        let tile = position.round_i32();
+        let seed = (tile.components[0] as u32).wrapping_mul(0x9e37_79b9) ^ (tile.components[1] as u32).wrapping_mul(0x85eb_ca6b) ^ 0x2545_f491;
+        // Xorshift never gets anywhere from zero.
+        Emitter { effect: effect, trigger: trigger, particles: Vec::new(), active: false, seed: seed.max(1) }
+    }
+
+    /// From 0 to 1, the same every run.
+    fn random(&mut self) -> f32
+    {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        (self.seed >> 8) as f32 / (1 << 24) as f32
+    }
+
+    /// Sends out a burst from `origin`, spread round `direction` or every
+    /// way.
+    pub fn burst(&mut self, origin: Vector2<f32>, direction: Option<Vector2<f32>>)
+    {
+        for _ in 0..self.effect.count
+        {
+            let angle = match direction
+            {
+                Some(direction) => direction.components[1].atan2(direction.components[0]) + (self.random() * 2.0 - 1.0) * self.effect.spread,
+                None => self.random() * 2.0 * PI
+            };
+            let heading = vec2(angle.cos(), angle.sin());
+            let speed = self.effect.speed[0] + (self.effect.speed[1] - self.effect.speed[0]) * self.random();
+            self.particles.push(Particle { position: origin + heading * self.effect.offset, velocity: heading * speed, age: 0.0 });
+        }
+    }
+
+    /// Bursts when `on` starts holding.
+    fn trigger_on(&mut self, on: bool, origin: Vector2<f32>, direction: Option<Vector2<f32>>)
+    {
+        if on && !self.active
+        {
+            self.burst(origin, direction);
+        }
+        self.active = on;
+    }
+
+    pub fn sprites(&self) -> Vec<ParticleSprite>
+    {
+        let effect = &self.effect;
+        self.particles.iter().map(|particle|
+        {
+            let t = (particle.age / effect.lifetime).min(1.0);
+            let mut color = [0.0; 4];
+            for (channel, value) in color.iter_mut().enumerate()
+            {
+                *value = effect.colors[0][channel] + (effect.colors[1][channel] - effect.colors[0][channel]) * t;
+            }
+            ParticleSprite
+            {
+                position: particle.position,
+                size: effect.size[0] + (effect.size[1] - effect.size[0]) * t,
+                region: vec2(effect.region[0], effect.region[1]),
+                color: color
+            }
+        }).collect()
+    }
+}
+
+/// Bursts emitters on blocks that start moving and buttons that get pressed.
+pub fn trigger_emitters(arg: RunArg)
+{
+    let (entities, mut emitter, position, motion, button) = arg.fetch(|w|
+        (w.entities(), w.write::<Emitter>(), w.read::<Position>(), w.read::<Motion>(), w.read::<Button>()));
+
+    for (entity, emitter, position) in (&entities, &mut emitter, &position).join()
+    {
+        match emitter.trigger
+        {
+            Trigger::Move =>
+            {
+                let destination = motion.get(entity).and_then(|motion| motion.destination);
+                let behind = destination.map(|destination| position.0 - destination.direction * 0.5);
+                emitter.trigger_on(destination.is_some(), behind.unwrap_or(position.0), destination.map(|destination| destination.direction * -1.0));
+            },
+            Trigger::Press =>
+            {
+                let pressed = button.get(entity).is_some_and(|button| button.0);
+                emitter.trigger_on(pressed, position.0, None);
+            },
+            Trigger::Caught => ()
+        }
+    }
+}
+
+/// Bursts the emitters on players that go off when they're caught.
+pub fn burst_caught(world: &World)
+{
+    let (mut emitter, position, player) = (world.write::<Emitter>().pass(), world.read::<Position>().pass(), world.read::<Player>().pass());
+    for (emitter, position, _) in (&mut emitter, &position, &player).join()
+    {
+        if emitter.trigger == Trigger::Caught
+        {
+            emitter.burst(position.0, None);
+        }
+    }
+}
+
+/// Ages and moves every particle, dropping the ones that have run out.
+pub fn update_particles(arg: RunArg, dt: f64)
+{
+    let mut emitter = arg.fetch(|w| w.write::<Emitter>());
+    let dt = dt as f32;
+
+    for emitter in (&mut emitter).join()
+    {
+        let (lifetime, gravity) = (emitter.effect.lifetime, emitter.effect.gravity);
+        emitter.particles.retain(|particle| particle.age + dt < lifetime);
+        for particle in &mut emitter.particles
+        {
+            particle.age += dt;
+            particle.velocity.components[1] -= gravity * dt;
+            particle.position = particle.position + particle.velocity * dt;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    pub fn bursts_fade_and_expire()
+    {
+        let effect: ParticleEffect = serde_yaml::from_str("
+            region: [0, 5]
+            count: 4
+            lifetime: 0.5
+            speed: [1.0, 1.0]
+            spread: 0.0
+            size: [0.5, 0.25]
+            colors: [[1, 1, 1, 1], [1, 1, 1, 0]]
+        ").unwrap();
+        let mut emitter = Emitter::new(effect.clone(), Trigger::Press, vec2(0.0, 0.0));
+
+        emitter.trigger_on(true, vec2(2.0, 3.0), Some(vec2(1.0, 0.0)));
+        emitter.trigger_on(true, vec2(2.0, 3.0), Some(vec2(1.0, 0.0)));
+        assert_eq!(emitter.particles.len(), 4);
+        assert_eq!(emitter.particles[0].velocity, vec2(1.0, 0.0));
+
+        emitter.particles[0].age = 0.25;
+        let sprites = emitter.sprites();
+        assert_eq!(sprites[0].size, 0.375);
+        assert_eq!(sprites[0].color[3], 0.5);
+
+        let (mut left, mut right) = (Emitter::new(effect.clone(), Trigger::Press, vec2(0.0, 0.0)), Emitter::new(effect, Trigger::Press, vec2(1.0, 0.0)));
+        left.burst(vec2(0.0, 0.0), None);
+        right.burst(vec2(0.0, 0.0), None);
+        assert_ne!(left.particles, right.particles);
+    }
+}
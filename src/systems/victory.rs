@@ -1,6 +1,7 @@
 use specs::{Component, World, Join, Gate};
 
-use systems::{Position, Player};
+use systems::{Position, Player, Button, Collision, BLOCK_CLASS};
+use vectors::*;
 
 pub struct Goal;
 component!(Goal);
@@ -9,7 +10,8 @@ pub struct Hazard;
 component!(Hazard);
 
 
-fn player_touched_something_of_type<T: Component>(world: &World) -> bool
+/// Where something of type T is that the player's on, if they're on one.
+fn thing_touching_player<T: Component>(world: &World) -> Option<Vector2<f32>>
 {
     let (position, player, thing) = (world.read::<Position>().pass(), world.read::<Player>().pass(), world.read::<T>().pass());
     for (player_position, _player) in (&position, &player).join()
@@ -20,19 +22,47 @@ fn player_touched_something_of_type<T: Component>(world: &World) -> bool
             let thing_pos = thing_position.0.round_i32();
             if player_pos == thing_pos
             {
-                return true;
+                return Some(thing_position.0);
             }
         }
     }
-    false
+    None
 }
 
 pub fn determine_gameover_from_hazard(world: &World) -> bool
 {
-    player_touched_something_of_type::<Hazard>(world)
+    hazard_touching_player(world).is_some()
+}
+
+/// Where the hazard that caught the player is.
+pub fn hazard_touching_player(world: &World) -> Option<Vector2<f32>>
+{
+    thing_touching_player::<Hazard>(world)
 }
 
 pub fn determine_victory_from_goal(world: &World) -> bool
 {
-    player_touched_something_of_type::<Goal>(world)
+    thing_touching_player::<Goal>(world).is_some()
+}
+
+/// Every button has a block sitting on it. The player and the stalker don't
+/// count, even though they hold buttons down too.
+pub fn determine_victory_from_buttons(world: &World) -> bool
+{
+    let (held, total) = buttons_held_by_blocks(world);
+    total > 0 && held == total
+}
+
+/// How many buttons have a block on them, out of how many there are.
+pub fn buttons_held_by_blocks(world: &World) -> (u32, u32)
+{
+    let (position, button, collision) = (world.read::<Position>().pass(), world.read::<Button>().pass(), world.read::<Collision>().pass());
+    let blocks: Vec<_> = (&position, &collision).join()
+        .filter(|&(_, collision)| collision.class & BLOCK_CLASS != 0)
+        .map(|(position, _)| position.0.round_i32())
+        .collect();
+
+    let buttons: Vec<_> = (&position, &button).join().map(|(position, _)| position.0.round_i32()).collect();
+    let held = buttons.iter().filter(|tile| blocks.contains(tile)).count();
+    (held as u32, buttons.len() as u32)
 }
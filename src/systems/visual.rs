@@ -4,7 +4,25 @@ pub const BG_LAYER: u32 = 0;
 pub const OBJECT_LAYER: u32 = 1;
 pub const ACTOR_LAYER: u32 = 2;
 pub const FG_LAYER: u32 = 3;
+/// Text and overlays, over everything in the level.
+pub const UI_LAYER: u32 = 4;
 
+/// An empty cell of the atlas, for things that shouldn't currently be seen.
+pub const BLANK_REGION: Vector2<u32> = Vector2 { components: [7, 7] };
+
+pub fn layer_from_name(name: &str) -> Option<u32>
+{
+    match name
+    {
+        "background" => Some(BG_LAYER),
+        "object" => Some(OBJECT_LAYER),
+        "actor" => Some(ACTOR_LAYER),
+        "foreground" => Some(FG_LAYER),
+        _ => None
+    }
+}
+
+#[derive(Clone)]
 pub struct Sprite
 {
     pub region: Vector2<u32>,
@@ -7,7 +7,7 @@ pub trait Dot<RHS=Self>
     fn dot(self, rhs: RHS) -> Self::Output;
 }
 
-#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
 pub struct Vector2<T: Copy>
 {
     pub components: [T; 2]